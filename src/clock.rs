@@ -0,0 +1,29 @@
+use time::OffsetDateTime;
+
+/// A source of the current time used for the `ts` field.
+///
+/// The default is [`SystemClock`]. A fixed clock can be set with
+/// [`Builder::with_clock`](crate::Builder::with_clock) to get deterministic
+/// output, e.g. in tests. Any `Fn() -> OffsetDateTime` can be used as a clock.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> OffsetDateTime;
+}
+
+/// The system clock, in UTC.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+impl<F> Clock for F
+where
+    F: Fn() -> OffsetDateTime + Send + Sync,
+{
+    fn now(&self) -> OffsetDateTime {
+        self()
+    }
+}
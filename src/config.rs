@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+
+use tracing::Level;
+
+#[cfg(feature = "ansi_logs")]
+use crate::{AnsiMode, LevelColors};
+use crate::{
+    BuiltinField, BytesEncoding, DuplicatePolicy, DurationFormat, DurationUnit, EscapeMode,
+    FmtSpan, KeyCase, LineEnding, MetadataPosition, OutputFormat, SpanRef, TimestampFormat,
+};
+
+/// All settings of the [`Builder`](crate::Builder) that can be described as
+/// data, for setting up the formatter from a configuration file with
+/// [`Builder::from_config`](crate::Builder::from_config).
+///
+/// Each field corresponds to the builder method with the same name prefixed
+/// with `with_`, unless its documentation links another one, and defaults to
+/// the same value as the builder does. With the `serde` feature the struct
+/// can be deserialized, and missing fields are set to their default.
+///
+/// Example, with a TOML file:
+/// ```toml
+/// target = false
+/// location = true
+/// verbose_level = "debug"
+/// field_renames = { message = "msg" }
+/// span_events = ["new", "close"]
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+#[non_exhaustive]
+pub struct LogfmtConfig {
+    pub level: bool,
+    pub level_and_severity: bool,
+    pub verbosity_field: Option<String>,
+    pub short_level: bool,
+    pub level_key: String,
+    /// Set with
+    /// [`Builder::with_target_level_override`](crate::Builder::with_target_level_override).
+    #[cfg_attr(feature = "serde", serde(with = "level_map"))]
+    pub target_level_overrides: HashMap<String, Level>,
+    pub kind: bool,
+    pub event_name: bool,
+    pub target: bool,
+    pub quoted_target: bool,
+    pub crate_name: bool,
+    #[cfg_attr(feature = "serde", serde(with = "option_level"))]
+    pub verbose_level: Option<Level>,
+    pub span_name: bool,
+    pub span_ref: SpanRef,
+    pub span_name_id_combined: bool,
+    pub span_name_id_joiner: char,
+    pub span_path: bool,
+    pub quoted_span_path: bool,
+    /// Set with [`Builder::with_metadata_quoting`](crate::Builder::with_metadata_quoting).
+    pub quoted_metadata: bool,
+    pub span_path_ids: bool,
+    pub span_fields_once: bool,
+    pub span_fields_grouped: bool,
+    pub span_field_count: bool,
+    pub root_span_fields: bool,
+    pub max_fields: Option<usize>,
+    pub location: bool,
+    pub caller: bool,
+    pub module_path: bool,
+    pub timestamp: bool,
+    pub timestamp_from_field: Option<String>,
+    pub timestamp_nanos_field: Option<String>,
+    /// Set with
+    /// [`Builder::with_additional_timestamp`](crate::Builder::with_additional_timestamp),
+    /// once per entry.
+    pub additional_timestamps: Vec<(String, TimestampFormat)>,
+    /// Empty for the default order.
+    pub field_order: Vec<BuiltinField>,
+    pub metadata_position: MetadataPosition,
+    pub sequence_numbers: bool,
+    pub uptime: bool,
+    #[cfg(feature = "event_id")]
+    pub event_id: bool,
+    #[cfg(feature = "otel")]
+    pub otel_ids: bool,
+    pub duration_suffix_parsing: bool,
+    pub duration_unit: DurationUnit,
+    /// Empty for no span events.
+    pub span_events: Vec<SpanEvent>,
+    pub span_duration_format: Option<DurationFormat>,
+    pub duplicate_field_policy: DuplicatePolicy,
+    pub default_message: Option<String>,
+    pub skip_blank_message: bool,
+    pub bare_message: bool,
+    pub fields: bool,
+    pub field_grouping: bool,
+    pub line_ending: LineEnding,
+    pub trailing_newline: bool,
+    pub line_suffix: Option<String>,
+    /// Set with [`Builder::with_field_rename`](crate::Builder::with_field_rename), once per entry.
+    pub field_renames: HashMap<String, String>,
+    pub version: Option<String>,
+    pub git_sha: Option<String>,
+    /// Set with [`Builder::with_static_field`](crate::Builder::with_static_field), once per entry.
+    pub static_fields: Vec<(String, String)>,
+    /// Set with
+    /// [`Builder::with_static_fields_from_env`](crate::Builder::with_static_fields_from_env).
+    pub static_fields_env_prefix: Option<String>,
+    /// Set with
+    /// [`Builder::with_promoted_span_field`](crate::Builder::with_promoted_span_field),
+    /// once per entry.
+    pub promoted_span_fields: Vec<(String, String)>,
+    pub field_denylist: Vec<String>,
+    pub filtered_count: bool,
+    pub max_value_length: Option<usize>,
+    pub error_field: bool,
+    pub quote_numbers: bool,
+    pub skip_none_debug: bool,
+    pub type_hints: bool,
+    pub pretty_debug: bool,
+    pub trim_values: bool,
+    pub bytes_encoding: BytesEncoding,
+    pub dotted_fields: bool,
+    pub fallback_key: Option<String>,
+    #[cfg(feature = "ansi_logs")]
+    pub ansi_mode: AnsiMode,
+    #[cfg(feature = "ansi_logs")]
+    pub level_colors: LevelColors,
+    pub quoted_keys: bool,
+    pub escape_mode: EscapeMode,
+    pub key_case: KeyCase,
+    pub output_format: OutputFormat,
+    pub quote_chars: Vec<char>,
+    pub escape_equals: bool,
+    pub quote_char: char,
+    pub strip_newlines: bool,
+    /// `None` for the default placeholder.
+    pub newline_placeholder: Option<String>,
+}
+
+impl Default for LogfmtConfig {
+    fn default() -> Self {
+        Self {
+            level: true,
+            level_and_severity: false,
+            verbosity_field: None,
+            short_level: false,
+            level_key: "level".to_owned(),
+            target_level_overrides: HashMap::new(),
+            kind: false,
+            event_name: false,
+            target: true,
+            quoted_target: false,
+            crate_name: false,
+            verbose_level: None,
+            span_name: true,
+            span_ref: SpanRef::default(),
+            span_name_id_combined: false,
+            span_name_id_joiner: '#',
+            span_path: true,
+            quoted_span_path: false,
+            quoted_metadata: false,
+            span_path_ids: false,
+            span_fields_once: false,
+            span_fields_grouped: false,
+            span_field_count: false,
+            root_span_fields: false,
+            max_fields: None,
+            location: false,
+            caller: false,
+            module_path: false,
+            timestamp: true,
+            timestamp_from_field: None,
+            timestamp_nanos_field: None,
+            additional_timestamps: Vec::new(),
+            field_order: Vec::new(),
+            metadata_position: MetadataPosition::default(),
+            sequence_numbers: false,
+            uptime: false,
+            #[cfg(feature = "event_id")]
+            event_id: false,
+            #[cfg(feature = "otel")]
+            otel_ids: false,
+            duration_suffix_parsing: false,
+            duration_unit: DurationUnit::default(),
+            span_events: Vec::new(),
+            span_duration_format: None,
+            duplicate_field_policy: DuplicatePolicy::default(),
+            default_message: None,
+            skip_blank_message: false,
+            bare_message: false,
+            fields: true,
+            field_grouping: false,
+            line_ending: LineEnding::default(),
+            trailing_newline: true,
+            line_suffix: None,
+            field_renames: HashMap::new(),
+            version: None,
+            git_sha: None,
+            static_fields: Vec::new(),
+            static_fields_env_prefix: None,
+            promoted_span_fields: Vec::new(),
+            field_denylist: Vec::new(),
+            filtered_count: false,
+            max_value_length: None,
+            error_field: false,
+            quote_numbers: false,
+            skip_none_debug: false,
+            type_hints: false,
+            pretty_debug: false,
+            trim_values: false,
+            bytes_encoding: BytesEncoding::default(),
+            dotted_fields: true,
+            fallback_key: None,
+            #[cfg(feature = "ansi_logs")]
+            ansi_mode: AnsiMode::default(),
+            #[cfg(feature = "ansi_logs")]
+            level_colors: LevelColors::default(),
+            quoted_keys: false,
+            escape_mode: EscapeMode::default(),
+            key_case: KeyCase::default(),
+            output_format: OutputFormat::default(),
+            quote_chars: Vec::new(),
+            escape_equals: false,
+            quote_char: '"',
+            strip_newlines: false,
+            newline_placeholder: None,
+        }
+    }
+}
+
+/// A span lifecycle event, or a set of them, for
+/// [`LogfmtConfig::span_events`]. Each one corresponds to the [`FmtSpan`]
+/// constant of the same name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum SpanEvent {
+    New,
+    Close,
+    Enter,
+    Exit,
+    /// Both `enter` and `exit`.
+    Active,
+    /// All of the events.
+    Full,
+}
+
+impl SpanEvent {
+    /// Each single event, in the order they happen to a span.
+    const SINGLE: [SpanEvent; 4] = [
+        SpanEvent::New,
+        SpanEvent::Enter,
+        SpanEvent::Exit,
+        SpanEvent::Close,
+    ];
+
+    /// The single events that are part of `span_events`.
+    pub(crate) fn from_fmt_span(span_events: FmtSpan) -> Vec<SpanEvent> {
+        Self::SINGLE
+            .into_iter()
+            .filter(|event| span_events.clone() & FmtSpan::from(*event) != FmtSpan::NONE)
+            .collect()
+    }
+}
+
+impl From<SpanEvent> for FmtSpan {
+    fn from(event: SpanEvent) -> Self {
+        match event {
+            SpanEvent::New => FmtSpan::NEW,
+            SpanEvent::Close => FmtSpan::CLOSE,
+            SpanEvent::Enter => FmtSpan::ENTER,
+            SpanEvent::Exit => FmtSpan::EXIT,
+            SpanEvent::Active => FmtSpan::ACTIVE,
+            SpanEvent::Full => FmtSpan::FULL,
+        }
+    }
+}
+
+/// Levels are written as their name, and read case-insensitively.
+#[cfg(feature = "serde")]
+mod option_level {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+    use tracing::Level;
+
+    pub(super) fn serialize<S>(level: &Option<Level>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        level.map(|level| level.as_str()).serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<Option<Level>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|level| level.parse().map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(feature = "serde")]
+mod level_map {
+    use std::collections::HashMap;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+    use tracing::Level;
+
+    pub(super) fn serialize<S>(
+        levels: &HashMap<String, Level>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        levels
+            .iter()
+            .map(|(key, level)| (key, level.as_str()))
+            .collect::<HashMap<_, _>>()
+            .serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<HashMap<String, Level>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        HashMap::<String, String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(key, level)| Ok((key, level.parse().map_err(D::Error::custom)?)))
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize() {
+        let config: LogfmtConfig = serde_json::from_str(
+            r#"{
+                "target": false,
+                "verbose_level": "debug",
+                "target_level_overrides": { "noisy": "DEBUG" },
+                "span_ref": "root",
+                "field_order": ["level", "ts"],
+                "span_events": ["active", "close"],
+                "field_renames": { "message": "msg" }
+            }"#,
+        )
+        .unwrap();
+
+        let expected = LogfmtConfig {
+            target: false,
+            verbose_level: Some(Level::DEBUG),
+            target_level_overrides: [("noisy".to_owned(), Level::DEBUG)].into(),
+            span_ref: SpanRef::Root,
+            field_order: vec![BuiltinField::Level, BuiltinField::Ts],
+            span_events: vec![SpanEvent::Active, SpanEvent::Close],
+            field_renames: [("message".to_owned(), "msg".to_owned())].into(),
+            ..Default::default()
+        };
+
+        assert_eq!(config, expected);
+
+        let roundtrip = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            serde_json::from_str::<LogfmtConfig>(&roundtrip).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn test_deserialize_unknown_field() {
+        assert!(serde_json::from_str::<LogfmtConfig>(r#"{ "colour": true }"#).is_err());
+    }
+}
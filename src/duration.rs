@@ -0,0 +1,214 @@
+use std::fmt;
+
+/// The unit used when re-emitting a parsed duration as a number.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum DurationUnit {
+    Nanoseconds,
+    Microseconds,
+    #[default]
+    Milliseconds,
+    Seconds,
+}
+
+impl DurationUnit {
+    /// Suffix appended to the field name, e.g. `elapsed_ms`.
+    pub(crate) fn suffix(self) -> &'static str {
+        match self {
+            DurationUnit::Nanoseconds => "ns",
+            DurationUnit::Microseconds => "us",
+            DurationUnit::Milliseconds => "ms",
+            DurationUnit::Seconds => "s",
+        }
+    }
+
+    fn nanos(self) -> u128 {
+        match self {
+            DurationUnit::Nanoseconds => 1,
+            DurationUnit::Microseconds => 1_000,
+            DurationUnit::Milliseconds => 1_000_000,
+            DurationUnit::Seconds => 1_000_000_000,
+        }
+    }
+}
+
+/// How the `time.busy` and `time.idle` fields of span close events are
+/// written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum DurationFormat {
+    /// Whole nanoseconds, e.g. `time.busy_ns=1250000000`.
+    Nanos,
+    /// Milliseconds, e.g. `time.busy_ms=1250`.
+    Millis,
+    /// A single token in the largest unit that fits, e.g. `time.busy=1.25s`.
+    Human,
+}
+
+/// Parses the `Debug` representation of a [`std::time::Duration`] (`1.5s`,
+/// `200ms`, `3µs`, `10ns`) into a number of nanoseconds.
+///
+/// The fractional part is handled with integer arithmetic so that the result
+/// is exact for anything `Duration`'s `Debug` impl can produce.
+pub(crate) fn parse_debug_duration(value: &str) -> Option<u128> {
+    let split = value
+        .find(|ch: char| !ch.is_ascii_digit() && ch != '.')
+        .unwrap_or(value.len());
+    let (number, suffix) = value.split_at(split);
+
+    let factor: u128 = match suffix {
+        "s" => 1_000_000_000,
+        "ms" => 1_000_000,
+        "µs" | "us" => 1_000,
+        "ns" => 1,
+        _ => return None,
+    };
+
+    let (whole, fraction) = match number.split_once('.') {
+        Some((whole, fraction)) => (whole, fraction),
+        None => (number, ""),
+    };
+    if whole.is_empty() || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let mut nanos = whole.parse::<u128>().ok()?.checked_mul(factor)?;
+
+    // digits beyond nanosecond precision are dropped
+    let mut scale = factor;
+    for digit in fraction.bytes() {
+        scale /= 10;
+        if scale == 0 {
+            break;
+        }
+        nanos += u128::from(digit - b'0') * scale;
+    }
+
+    Some(nanos)
+}
+
+/// Displays a number of nanoseconds in the given unit as an exact decimal,
+/// without trailing zeros in the fractional part.
+pub(crate) struct DisplayInUnit {
+    pub(crate) nanos: u128,
+    pub(crate) unit: DurationUnit,
+}
+
+impl fmt::Display for DisplayInUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit_nanos = self.unit.nanos();
+        let whole = self.nanos / unit_nanos;
+        let mut rem = self.nanos % unit_nanos;
+
+        write!(f, "{}", whole)?;
+
+        if rem > 0 {
+            f.write_str(".")?;
+            let mut scale = unit_nanos / 10;
+            while rem > 0 {
+                write!(f, "{}", rem / scale)?;
+                rem %= scale;
+                scale /= 10;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Displays a number of nanoseconds in the largest unit that keeps the whole
+/// part non-zero, with the unit as suffix, e.g. `1.25s` or `130µs`.
+pub(crate) struct DisplayHuman(pub(crate) u128);
+
+impl fmt::Display for DisplayHuman {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (unit, suffix) = match self.0 {
+            n if n >= 1_000_000_000 => (DurationUnit::Seconds, "s"),
+            n if n >= 1_000_000 => (DurationUnit::Milliseconds, "ms"),
+            n if n >= 1_000 => (DurationUnit::Microseconds, "µs"),
+            _ => (DurationUnit::Nanoseconds, "ns"),
+        };
+        write!(
+            f,
+            "{}{}",
+            DisplayInUnit {
+                nanos: self.0,
+                unit
+            },
+            suffix
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_debug_duration() {
+        let tests = vec![
+            (Duration::from_millis(1500), Some(1_500_000_000)),
+            (Duration::from_millis(200), Some(200_000_000)),
+            (Duration::from_micros(3), Some(3_000)),
+            (Duration::from_nanos(10), Some(10)),
+            (Duration::from_nanos(1_000_000_001), Some(1_000_000_001)),
+            (Duration::from_nanos(1_234_567), Some(1_234_567)),
+            (Duration::ZERO, Some(0)),
+        ];
+
+        for (input, expected_output) in tests {
+            assert_eq!(
+                parse_debug_duration(&format!("{:?}", input)),
+                expected_output
+            );
+        }
+
+        for input in ["", "s", ".5s", "5", "5m", "1.5.5s", "abc", "-5s"] {
+            assert_eq!(parse_debug_duration(input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_display_in_unit() {
+        let tests = vec![
+            (1_500_000_000, DurationUnit::Milliseconds, "1500"),
+            (3_000, DurationUnit::Milliseconds, "0.003"),
+            (1_234_567, DurationUnit::Milliseconds, "1.234567"),
+            (1_500_000_000, DurationUnit::Seconds, "1.5"),
+            (1_500, DurationUnit::Microseconds, "1.5"),
+            (10, DurationUnit::Nanoseconds, "10"),
+            (0, DurationUnit::Seconds, "0"),
+        ];
+
+        for (nanos, unit, expected_output) in tests {
+            assert_eq!(DisplayInUnit { nanos, unit }.to_string(), expected_output);
+        }
+    }
+
+    #[test]
+    fn test_display_human() {
+        let tests = vec![
+            (1_250_000_000, "1.25s"),
+            (130_000, "130µs"),
+            (7, "7ns"),
+            (0, "0ns"),
+            (3_450_000, "3.45ms"),
+        ];
+
+        for (nanos, expected_output) in tests {
+            assert_eq!(DisplayHuman(nanos).to_string(), expected_output);
+        }
+    }
+}
@@ -1,16 +1,25 @@
 pub(crate) mod builder;
 
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 
 use tracing::field::Visit;
-use tracing_core::{Event, Field, Subscriber};
+use tracing_core::{Event, Field, Metadata, Subscriber};
 use tracing_subscriber::field::RecordFields;
 use tracing_subscriber::fmt::format::{self, FormatEvent, FormatFields};
 use tracing_subscriber::fmt::{FmtContext, FormattedFields};
-use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::registry::{self, LookupSpan};
 use tracing_subscriber::Layer;
 
-use crate::serializer::{Serializer, SerializerError};
+use crate::clock::{Clock, SystemClock};
+use crate::duration::{self, DurationFormat, DurationUnit};
+use crate::serializer::{Serializer, SerializerError, SerializerOptions};
 
 /// Creates a formatting layer
 ///
@@ -34,660 +43,5882 @@ where
 }
 
 /// A formatter that formats tracing-subscriber events into logfmt formatted log rows.
+///
+/// Cloning the formatter shares any runtime state, such as the sequence counter
+/// used by [`Builder::with_sequence_numbers`](crate::Builder::with_sequence_numbers).
+#[derive(Clone)]
 pub struct EventsFormatter {
+    /// Tells apart formatters that share a registry, see [`SpanFieldsWritten`].
+    pub(crate) instance_id: u64,
     pub(crate) with_level: bool,
+    pub(crate) with_level_and_severity: bool,
+    pub(crate) verbosity_field: Option<String>,
+    pub(crate) with_short_level: bool,
+    pub(crate) level_key: String,
+    pub(crate) extra_level_fields: Vec<ExtraLevelField>,
+    pub(crate) version: Option<String>,
+    pub(crate) git_sha: Option<String>,
+    pub(crate) static_fields: Vec<(String, String)>,
+    pub(crate) dynamic_fields: Vec<DynamicFields>,
+    pub(crate) promoted_span_fields: Vec<(String, String)>,
+    pub(crate) with_kind: bool,
+    pub(crate) with_event_name: bool,
     pub(crate) with_target: bool,
+    pub(crate) with_quoted_target: bool,
+    pub(crate) with_crate_name: bool,
+    pub(crate) verbose_level: Option<tracing::Level>,
+    pub(crate) target_level_overrides: HashMap<String, tracing::Level>,
     pub(crate) with_span_name: bool,
+    pub(crate) span_ref: SpanRef,
+    pub(crate) with_span_name_id_combined: bool,
+    pub(crate) span_name_id_joiner: char,
     pub(crate) with_span_path: bool,
+    pub(crate) with_quoted_span_path: bool,
+    pub(crate) with_quoted_metadata: bool,
+    pub(crate) with_span_path_ids: bool,
+    pub(crate) with_span_fields_once: bool,
+    pub(crate) with_span_fields_grouped: bool,
+    pub(crate) with_span_field_count: bool,
+    pub(crate) with_filtered_count: bool,
+    pub(crate) max_fields: Option<usize>,
+    pub(crate) with_root_span_fields: bool,
     pub(crate) with_location: bool,
+    pub(crate) with_caller: bool,
     pub(crate) with_module_path: bool,
     pub(crate) with_timestamp: bool,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) timestamp_from_field: Option<String>,
+    pub(crate) timestamp_nanos_field: Option<String>,
+    pub(crate) additional_timestamps: Vec<(String, TimestampFormat)>,
+    pub(crate) field_order: Vec<BuiltinField>,
+    pub(crate) metadata_position: MetadataPosition,
+    pub(crate) with_sequence_numbers: bool,
+    pub(crate) sequence: Arc<AtomicU64>,
+    pub(crate) with_uptime: bool,
+    /// When the formatter was created, `uptime_ms` is measured from here.
+    pub(crate) start: Instant,
+    #[cfg(feature = "event_id")]
+    pub(crate) with_event_id: bool,
+    #[cfg(feature = "otel")]
+    pub(crate) with_otel_ids: bool,
+    pub(crate) with_duration_suffix_parsing: bool,
+    pub(crate) duration_unit: DurationUnit,
+    pub(crate) span_duration_format: Option<DurationFormat>,
+    pub(crate) duplicate_policy: DuplicatePolicy,
+    pub(crate) default_message: Option<String>,
+    pub(crate) line_ending: LineEnding,
+    pub(crate) with_trailing_newline: bool,
+    pub(crate) line_suffix: Option<String>,
+    pub(crate) with_skip_blank_message: bool,
+    pub(crate) with_bare_message: bool,
+    pub(crate) with_fields: bool,
+    pub(crate) with_field_grouping: bool,
+    pub(crate) field_sort: Option<FieldSort>,
+    pub(crate) field_options: FieldOptions,
+    pub(crate) serializer: SerializerOptions,
     #[cfg(feature = "ansi_logs")]
     pub(crate) with_ansi_color: bool,
+    #[cfg(feature = "ansi_logs")]
+    pub(crate) level_colors: LevelColors,
 }
 
 impl Default for EventsFormatter {
     fn default() -> Self {
         Self {
             with_level: true,
+            with_level_and_severity: false,
+            verbosity_field: None,
+            with_short_level: false,
+            level_key: "level".to_owned(),
+            extra_level_fields: Vec::new(),
+            version: None,
+            git_sha: None,
+            static_fields: Vec::new(),
+            dynamic_fields: Vec::new(),
+            promoted_span_fields: Vec::new(),
+            with_kind: false,
+            with_event_name: false,
             with_target: true,
+            with_quoted_target: false,
+            with_crate_name: false,
+            verbose_level: None,
+            target_level_overrides: HashMap::new(),
             with_span_name: true,
+            span_ref: SpanRef::Leaf,
+            with_span_name_id_combined: false,
+            span_name_id_joiner: '#',
             with_span_path: true,
+            with_quoted_span_path: false,
+            with_quoted_metadata: false,
+            with_span_path_ids: false,
+            with_span_fields_once: false,
+            with_span_fields_grouped: false,
+            with_span_field_count: false,
+            with_filtered_count: false,
+            max_fields: None,
+            with_root_span_fields: false,
             with_location: false,
+            with_caller: false,
             with_module_path: false,
             with_timestamp: true,
+            clock: Arc::new(SystemClock),
+            timestamp_from_field: None,
+            timestamp_nanos_field: None,
+            additional_timestamps: Vec::new(),
+            field_order: BuiltinField::DEFAULT_ORDER.to_vec(),
+            metadata_position: MetadataPosition::Prefix,
+            instance_id: next_instance_id(),
+            with_sequence_numbers: false,
+            sequence: Arc::new(AtomicU64::new(0)),
+            with_uptime: false,
+            start: Instant::now(),
+            #[cfg(feature = "event_id")]
+            with_event_id: false,
+            #[cfg(feature = "otel")]
+            with_otel_ids: false,
+            with_duration_suffix_parsing: false,
+            duration_unit: DurationUnit::default(),
+            span_duration_format: None,
+            duplicate_policy: DuplicatePolicy::KeepAll,
+            default_message: None,
+            line_ending: LineEnding::Lf,
+            with_trailing_newline: true,
+            line_suffix: None,
+            with_skip_blank_message: false,
+            with_bare_message: false,
+            with_fields: true,
+            with_field_grouping: false,
+            field_sort: None,
+            field_options: FieldOptions::default(),
+            serializer: SerializerOptions::default(),
             #[cfg(feature = "ansi_logs")]
             with_ansi_color: default_enable_ansi_color(),
+            #[cfg(feature = "ansi_logs")]
+            level_colors: LevelColors::default(),
         }
     }
 }
 
-#[cfg(feature = "ansi_logs")]
-fn default_enable_ansi_color() -> bool {
-    use std::io::IsTerminal;
-    std::io::stdout().is_terminal()
-}
-
-impl<S, N> FormatEvent<S, N> for EventsFormatter
-where
-    S: Subscriber + for<'a> LookupSpan<'a>,
-    N: for<'a> FormatFields<'a> + 'static,
-{
-    fn format_event(
-        &self,
-        ctx: &FmtContext<'_, S, N>,
-        mut writer: format::Writer<'_>,
-        event: &Event<'_>,
-    ) -> fmt::Result {
-        let mut serializer = Serializer::new(
-            &mut writer,
-            #[cfg(feature = "ansi_logs")]
-            self.with_ansi_color,
-        );
-
-        let mut visit = || {
-            let metadata = event.metadata();
-
-            if self.with_timestamp {
-                serializer.serialize_key("ts")?;
-                serializer.writer.write_char('=')?;
-                time::OffsetDateTime::now_utc()
-                    .format_into(
-                        &mut serializer,
-                        &time::format_description::well_known::Rfc3339,
-                    )
-                    .map_err(|_e| fmt::Error)?;
-            }
-
-            if self.with_level {
-                let level = match *metadata.level() {
-                    tracing::Level::ERROR => "error",
-                    tracing::Level::WARN => "warn",
-                    tracing::Level::INFO => "info",
-                    tracing::Level::DEBUG => "debug",
-                    tracing::Level::TRACE => "trace",
-                };
+/// A key and a function that formats the level of the event as its value, see
+/// [`Builder::with_extra_level_field`](crate::Builder::with_extra_level_field).
+pub(crate) type ExtraLevelField = (String, Arc<dyn Fn(tracing::Level) -> String + Send + Sync>);
 
-                #[cfg(feature = "ansi_logs")]
-                {
-                    if self.with_ansi_color {
-                        let level_str = match *metadata.level() {
-                            tracing::Level::ERROR => nu_ansi_term::Color::Red,
-                            tracing::Level::WARN => nu_ansi_term::Color::Yellow,
-                            tracing::Level::INFO => nu_ansi_term::Color::Green,
-                            tracing::Level::DEBUG => nu_ansi_term::Color::Blue,
-                            tracing::Level::TRACE => nu_ansi_term::Color::Purple,
-                        }
-                        .bold()
-                        .paint(level);
+/// A function that returns fields to add to every line, see
+/// [`Builder::with_dynamic_fields`](crate::Builder::with_dynamic_fields).
+pub(crate) type DynamicFields = Arc<dyn Fn() -> Vec<(String, String)> + Send + Sync>;
 
-                        serializer.serialize_entry("level", &level_str.to_string())?;
-                    } else {
-                        serializer.serialize_entry("level", level)?;
-                    }
-                }
+/// A function that orders the fields of an event by key, see
+/// [`Builder::with_field_sort`](crate::Builder::with_field_sort).
+pub(crate) type FieldSort = Arc<dyn Fn(&str, &str) -> cmp::Ordering + Send + Sync>;
 
-                #[cfg(not(feature = "ansi_logs"))]
-                serializer.serialize_entry("level", level)?;
-            }
+/// A function that replaces or drops field values, see
+/// [`Builder::with_value_transform`](crate::Builder::with_value_transform).
+pub(crate) type ValueTransform =
+    Arc<dyn for<'v> Fn(&str, &'v str) -> Option<Cow<'v, str>> + Send + Sync>;
 
-            if self.with_target {
-                serializer.serialize_entry("target", metadata.target())?;
-            }
+/// Which span of the current scope is reported in the `span` field.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum SpanRef {
+    /// The span the event was recorded in, i.e. the innermost span.
+    #[default]
+    Leaf,
+    /// The outermost span of the scope the event was recorded in.
+    Root,
+}
 
-            let span = if self.with_span_name || self.with_span_path {
-                event
-                    .parent()
-                    .and_then(|id| ctx.span(id))
-                    .or_else(|| ctx.lookup_current())
-            } else {
-                None
-            };
+/// What to do when an event records the same field name more than once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum DuplicatePolicy {
+    /// Write every occurrence of the field.
+    #[default]
+    KeepAll,
+    /// Only write the first occurrence of the field.
+    KeepFirst,
+    /// Only write the last occurrence of the field, at the position of the
+    /// last occurrence.
+    KeepLast,
+}
 
-            if self.with_location {
-                if let (Some(file), Some(line)) = (metadata.file(), metadata.line()) {
-                    serializer.serialize_entry("location", &format!("{}:{}", file, line))?;
-                }
-            }
-            if self.with_module_path {
-                if let Some(module) = metadata.module_path() {
-                    serializer.serialize_entry("module_path", module)?;
-                }
-            }
+/// The characters written at the end of every line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum LineEnding {
+    /// `\n`
+    #[default]
+    Lf,
+    /// `\r\n`
+    CrLf,
+}
 
-            if let Some(span) = span {
-                if self.with_span_name {
-                    serializer.serialize_entry("span", span.name())?;
-                }
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
 
-                if self.with_span_path {
-                    serializer.serialize_key("span_path")?;
-                    serializer.writer.write_char('=')?;
+/// How a timestamp added with
+/// [`Builder::with_additional_timestamp`](crate::Builder::with_additional_timestamp)
+/// is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum TimestampFormat {
+    /// Like the `ts` field, e.g. `2024-01-02T03:04:05.678Z`.
+    Rfc3339,
+    /// Whole seconds since the Unix epoch, e.g. `1704164645`.
+    UnixSeconds,
+    /// Whole milliseconds since the Unix epoch, e.g. `1704164645678`.
+    UnixMillis,
+    /// Whole microseconds since the Unix epoch.
+    UnixMicros,
+    /// Nanoseconds since the Unix epoch.
+    UnixNanos,
+}
 
-                    let needs_quote = span
-                        .scope()
-                        .from_root()
-                        .any(|span| span.name().chars().any(crate::serializer::need_quote));
+/// How byte slice fields are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum BytesEncoding {
+    /// Lowercase hex, e.g. `data=6869`.
+    #[default]
+    Hex,
+    /// Standard base64 without padding, e.g. `data=aGk`. The padding is left
+    /// out because `=` would force the value to be quoted.
+    Base64,
+}
 
-                    // if none of the span names need to be quoted we can do things a bit faster
-                    if needs_quote {
-                        let mut required_capacity = 0;
-                        let mut insert_sep = false;
-                        for span in span.scope().from_root() {
-                            if insert_sep {
-                                required_capacity += 1;
-                            }
-                            required_capacity += span.name().len();
-                            insert_sep = true;
-                        }
+/// Writes bytes with an encoding that never needs quoting.
+struct EncodedBytes<'b> {
+    bytes: &'b [u8],
+    encoding: BytesEncoding,
+}
 
-                        let mut span_path = String::with_capacity(required_capacity);
-                        let s = Serializer::new(
-                            &mut span_path,
-                            #[cfg(feature = "ansi_logs")]
-                            self.with_ansi_color,
-                        );
-                        let mut insert_sep = false;
-                        for span in span.scope().from_root() {
-                            if insert_sep {
-                                s.writer.write_char('>')?;
-                            }
-                            s.writer.write_str(span.name())?;
-                            insert_sep = true;
-                        }
-                        serializer.serialize_value(&span_path)?;
-                    } else {
-                        let mut insert_sep = false;
-                        for span in span.scope().from_root() {
-                            if insert_sep {
-                                serializer.writer.write_char('>')?;
-                            }
-                            serializer.writer.write_str(span.name())?;
-                            insert_sep = true;
-                        }
-                    }
+impl fmt::Debug for EncodedBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.encoding {
+            BytesEncoding::Hex => {
+                for byte in self.bytes {
+                    write!(f, "{:02x}", byte)?;
                 }
             }
+            BytesEncoding::Base64 => {
+                const ALPHABET: &[u8; 64] =
+                    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
 
-            let mut visitor = Visitor::new(&mut serializer);
-            event.record(&mut visitor);
-            visitor.state?;
-
-            Ok(())
-        };
-
-        visit().map_err(|_e: SerializerError| fmt::Error)?;
-
-        // Write all fields from spans
-        if let Some(leaf_span) = ctx.lookup_current() {
-            for span in leaf_span.scope().from_root() {
-                let ext = span.extensions();
-                let data = ext
-                    .get::<FormattedFields<N>>()
-                    .expect("Unable to find FormattedFields in extensions; this is a bug");
-
-                if !data.is_empty() {
-                    write!(writer, " ")?;
-                    write!(writer, "{}", data)?;
+                for chunk in self.bytes.chunks(3) {
+                    let group = chunk
+                        .iter()
+                        .enumerate()
+                        .fold(0u32, |group, (index, &byte)| {
+                            group | u32::from(byte) << (16 - 8 * index)
+                        });
+                    // 3 bytes are 4 characters, fewer bytes one character more
+                    // than there are bytes
+                    for index in 0..=chunk.len() {
+                        let sextet = (group >> (18 - 6 * index)) & 0x3f;
+                        f.write_char(char::from(ALPHABET[sextet as usize]))?;
+                    }
                 }
             }
         }
-
-        writeln!(writer)
+        Ok(())
     }
 }
 
-/// A formatter that formats span fields into logfmt.
-#[derive(Default)]
+/// Where the builtin metadata fields are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum MetadataPosition {
+    /// Before the fields of the event.
+    #[default]
+    Prefix,
+    /// After the fields of the event and its spans.
+    Suffix,
+}
+
+/// The builtin metadata fields that are written before (or, with
+/// [`MetadataPosition::Suffix`], after) the fields of the event.
+///
+/// Used with [`Builder::with_field_order`](crate::Builder::with_field_order) to
+/// control the order they are written in. Each field is only written if it is
+/// enabled in the builder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 #[non_exhaustive]
-pub struct FieldsFormatter {}
+pub enum BuiltinField {
+    /// `ts`
+    Ts,
+    /// `seq`
+    Seq,
+    /// `uptime_ms`
+    Uptime,
+    /// `event_id`
+    EventId,
+    /// `level`, and `severity` when enabled
+    Level,
+    /// `kind`
+    Kind,
+    /// `name`
+    EventName,
+    /// `target`
+    Target,
+    /// `crate`
+    Crate,
+    /// `location`
+    Location,
+    /// `caller`
+    Caller,
+    /// `module_path`
+    ModulePath,
+    /// `span`
+    Span,
+    /// `span_path`
+    SpanPath,
+    /// `trace_id` and `span_id`
+    OtelIds,
+    /// The fields added with
+    /// [`Builder::with_static_field`](crate::Builder::with_static_field)
+    Static,
+}
 
-impl<'writer> FormatFields<'writer> for FieldsFormatter {
-    fn format_fields<R: RecordFields>(
-        &self,
-        mut writer: format::Writer<'writer>,
-        fields: R,
-    ) -> fmt::Result {
-        let mut serializer = Serializer::new(
-            &mut writer,
-            #[cfg(feature = "ansi_logs")]
-            false,
-        );
-        let mut visitor = Visitor::new(&mut serializer);
-        fields.record(&mut visitor);
-        Ok(())
-    }
+impl BuiltinField {
+    pub(crate) const DEFAULT_ORDER: [BuiltinField; 16] = [
+        BuiltinField::Ts,
+        BuiltinField::Seq,
+        BuiltinField::Uptime,
+        BuiltinField::EventId,
+        BuiltinField::Level,
+        BuiltinField::Kind,
+        BuiltinField::EventName,
+        BuiltinField::Target,
+        BuiltinField::Crate,
+        BuiltinField::Location,
+        BuiltinField::Caller,
+        BuiltinField::ModulePath,
+        BuiltinField::Span,
+        BuiltinField::SpanPath,
+        BuiltinField::OtelIds,
+        BuiltinField::Static,
+    ];
 }
 
-struct Visitor<'a, W> {
-    serializer: &'a mut Serializer<W>,
-    state: Result<(), SerializerError>,
-    debug_fmt_buffer: String,
+#[cfg(feature = "ansi_logs")]
+fn default_enable_ansi_color() -> bool {
+    use std::io::IsTerminal;
+    AnsiMode::Auto.resolve(|| std::io::stdout().is_terminal())
 }
 
-impl<'a, W> Visitor<'a, W> {
-    fn new(serializer: &'a mut Serializer<W>) -> Self {
-        Self {
-            serializer,
-            state: Ok(()),
-            debug_fmt_buffer: String::new(),
-        }
-    }
+/// When ANSI colors are used.
+#[cfg(feature = "ansi_logs")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum AnsiMode {
+    /// Use colors if the output is a terminal. A non-empty `NO_COLOR`
+    /// environment variable disables them, and a `CLICOLOR_FORCE` that is
+    /// non-empty and not `0` enables them even if the output is not a
+    /// terminal.
+    #[default]
+    Auto,
+    /// Always use colors.
+    Always,
+    /// Never use colors.
+    Never,
 }
 
-impl<'a, W> Visit for Visitor<'a, W>
-where
-    W: fmt::Write,
-{
-    fn record_f64(&mut self, field: &Field, value: f64) {
-        if self.state.is_ok() {
-            self.record_debug_no_quote(field, value);
-        }
+#[cfg(feature = "ansi_logs")]
+impl AnsiMode {
+    /// Whether colors are used, where `is_terminal` tells whether the output
+    /// is a terminal.
+    pub(crate) fn resolve(self, is_terminal: impl FnOnce() -> bool) -> bool {
+        self.resolve_with_env(is_terminal, |name| std::env::var_os(name))
     }
 
-    fn record_i64(&mut self, field: &Field, value: i64) {
-        if self.state.is_ok() {
-            self.record_debug_no_quote(field, value);
+    fn resolve_with_env(
+        self,
+        is_terminal: impl FnOnce() -> bool,
+        var: impl Fn(&str) -> Option<std::ffi::OsString>,
+    ) -> bool {
+        match self {
+            AnsiMode::Always => true,
+            AnsiMode::Never => false,
+            AnsiMode::Auto => {
+                // https://no-color.org and https://bixense.com/clicolors
+                if var("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+                    return false;
+                }
+                if var("CLICOLOR_FORCE").is_some_and(|value| !value.is_empty() && value != "0") {
+                    return true;
+                }
+                is_terminal()
+            }
         }
     }
+}
 
-    fn record_u64(&mut self, field: &Field, value: u64) {
-        if self.state.is_ok() {
-            self.record_debug_no_quote(field, value);
-        }
-    }
+/// A color of the 16 color ANSI palette, see [`LevelColors`].
+#[cfg(feature = "ansi_logs")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum AnsiColor {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Purple,
+    Cyan,
+    White,
+    Gray,
+}
 
-    fn record_bool(&mut self, field: &Field, value: bool) {
-        if self.state.is_ok() {
-            self.record_debug_no_quote(field, value);
+#[cfg(feature = "ansi_logs")]
+impl AnsiColor {
+    fn to_nu_ansi_term(self) -> nu_ansi_term::Color {
+        match self {
+            AnsiColor::Black => nu_ansi_term::Color::Black,
+            AnsiColor::Red => nu_ansi_term::Color::Red,
+            AnsiColor::Green => nu_ansi_term::Color::Green,
+            AnsiColor::Yellow => nu_ansi_term::Color::Yellow,
+            AnsiColor::Blue => nu_ansi_term::Color::Blue,
+            AnsiColor::Purple => nu_ansi_term::Color::Purple,
+            AnsiColor::Cyan => nu_ansi_term::Color::Cyan,
+            AnsiColor::White => nu_ansi_term::Color::White,
+            AnsiColor::Gray => nu_ansi_term::Color::DarkGray,
         }
     }
+}
 
-    fn record_str(&mut self, field: &Field, value: &str) {
-        if self.state.is_ok() {
-            self.state = self.serializer.serialize_entry(field.name(), value);
-        }
-    }
+/// The colors the level is written in when ANSI colors are used.
+#[cfg(feature = "ansi_logs")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct LevelColors {
+    pub error: AnsiColor,
+    pub warn: AnsiColor,
+    pub info: AnsiColor,
+    pub debug: AnsiColor,
+    pub trace: AnsiColor,
+}
 
-    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
-        if self.state.is_ok() {
-            self.record_debug(field, &format_args!("{}", value));
+#[cfg(feature = "ansi_logs")]
+impl Default for LevelColors {
+    fn default() -> Self {
+        Self {
+            error: AnsiColor::Red,
+            warn: AnsiColor::Yellow,
+            info: AnsiColor::Green,
+            debug: AnsiColor::Blue,
+            trace: AnsiColor::Purple,
         }
     }
+}
 
-    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
-        if self.state.is_ok() {
-            self.debug_fmt_buffer.clear();
-            let _ = write!(self.debug_fmt_buffer, "{:?}", value);
-            self.state = self
-                .serializer
-                .serialize_entry(field.name(), &self.debug_fmt_buffer);
+#[cfg(feature = "ansi_logs")]
+impl LevelColors {
+    fn get(&self, level: tracing::Level) -> AnsiColor {
+        match level {
+            tracing::Level::ERROR => self.error,
+            tracing::Level::WARN => self.warn,
+            tracing::Level::INFO => self.info,
+            tracing::Level::DEBUG => self.debug,
+            tracing::Level::TRACE => self.trace,
         }
     }
 }
 
-impl<'a, W> Visitor<'a, W>
-where
-    W: fmt::Write,
-{
-    fn record_debug_no_quote(&mut self, field: &Field, value: impl fmt::Debug) {
-        if self.state.is_ok() {
-            self.state = self
-                .serializer
-                .serialize_entry_no_quote(field.name(), value);
-        }
+/// Maps a tracing level to the closest syslog severity (RFC 5424).
+///
+/// Syslog has no level below debug, so both `DEBUG` and `TRACE` map to 7.
+fn syslog_severity(level: &tracing::Level) -> u8 {
+    match *level {
+        tracing::Level::ERROR => 3,
+        tracing::Level::WARN => 4,
+        tracing::Level::INFO => 6,
+        tracing::Level::DEBUG | tracing::Level::TRACE => 7,
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::{
-        io,
-        sync::{Arc, Mutex},
-    };
-
-    use tracing::info_span;
-    use tracing_subscriber::fmt::{MakeWriter, SubscriberBuilder};
-
-    use super::*;
+/// Maps a tracing level to a verbosity where `ERROR` is 1 and `TRACE` is 5,
+/// the opposite order of [`syslog_severity`].
+fn verbosity(level: &tracing::Level) -> u8 {
+    match *level {
+        tracing::Level::ERROR => 1,
+        tracing::Level::WARN => 2,
+        tracing::Level::INFO => 3,
+        tracing::Level::DEBUG => 4,
+        tracing::Level::TRACE => 5,
+    }
+}
 
-    #[derive(Clone, Debug)]
-    struct MockWriter {
-        buf: Arc<Mutex<Vec<u8>>>,
+impl EventsFormatter {
+    /// Whether any timestamp is written, so that the clock has to be read.
+    fn writes_timestamp(&self) -> bool {
+        self.with_timestamp
+            || self.timestamp_nanos_field.is_some()
+            || !self.additional_timestamps.is_empty()
     }
 
-    #[derive(Clone, Debug)]
-    struct MockMakeWriter {
-        buf: Arc<Mutex<Vec<u8>>>,
+    /// How many of the fields of `span` this formatter has already written,
+    /// `None` if it hasn't written any.
+    fn span_fields_written<S>(&self, span: &registry::SpanRef<'_, S>) -> Option<WrittenFields>
+    where
+        S: for<'a> LookupSpan<'a>,
+    {
+        let extensions = span.extensions();
+        let written = extensions.get::<SpanFieldsWritten>()?;
+        written
+            .0
+            .iter()
+            .find(|(instance_id, _)| *instance_id == self.instance_id)
+            .map(|(_, fields)| *fields)
     }
 
-    impl MockMakeWriter {
-        fn new() -> Self {
-            Self {
-                buf: Arc::new(Mutex::new(Vec::new())),
+    /// Writes the formatted fields of a span, and returns how many of them
+    /// were written before reaching the limit of
+    /// [`Builder::with_max_fields`](crate::Builder::with_max_fields).
+    fn serialize_span_fields<W: fmt::Write>(
+        &self,
+        serializer: &mut Serializer<'_, W>,
+        name: &str,
+        data: &str,
+        remaining_fields: &mut Option<usize>,
+        span_field_count: &mut usize,
+    ) -> Result<WrittenFields, SerializerError> {
+        let entries = serializer.formatted_entries(data);
+        if self.with_span_fields_grouped {
+            // the whole group counts as one field for the limit
+            if let Some(remaining) = remaining_fields {
+                if *remaining == 0 {
+                    return Ok(WrittenFields::First(0));
+                }
+                *remaining -= 1;
+            }
+            if self.with_span_field_count {
+                *span_field_count += entries.count();
+            }
+            let data = serializer.trim_leading_separator(data);
+            match serialize_span_fields_grouped(serializer, name, data) {
+                Err(SerializerError::InvalidKey) => {}
+                result => result?,
+            }
+        } else if let Some(remaining) = remaining_fields {
+            // written one by one, to stop at the limit
+            for (written, (key, value)) in entries.enumerate() {
+                if *remaining == 0 {
+                    return Ok(WrittenFields::First(written));
+                }
+                *remaining -= 1;
+                *span_field_count += 1;
+                serializer.serialize_formatted_pair(key, value)?;
+            }
+        } else {
+            serializer.serialize_formatted_entries(serializer.trim_leading_separator(data))?;
+            if self.with_span_field_count {
+                *span_field_count += entries.count();
             }
         }
-        fn get_content(&self) -> String {
-            let buf = self.buf.lock().unwrap();
-            std::str::from_utf8(&buf[..]).unwrap().to_owned()
-        }
-    }
 
-    impl MockWriter {
-        fn new(buf: Arc<Mutex<Vec<u8>>>) -> Self {
-            Self { buf }
-        }
+        Ok(WrittenFields::All)
     }
 
-    impl io::Write for MockWriter {
-        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-            self.buf.lock().unwrap().write(buf)
+    /// Whether any of the enabled builtin fields is taken from the span of the
+    /// event.
+    fn needs_span(&self) -> bool {
+        #[cfg(feature = "otel")]
+        if self.with_otel_ids {
+            return true;
         }
 
-        fn flush(&mut self) -> io::Result<()> {
-            self.buf.lock().unwrap().flush()
-        }
+        self.with_span_name || self.with_span_path
     }
 
-    impl<'a> MakeWriter<'a> for MockMakeWriter {
-        type Writer = MockWriter;
+    /// Whether the verbose metadata fields (`target`, `location`, `caller` and
+    /// `span_path`) are written for an event with this metadata.
+    fn is_verbose(&self, metadata: &Metadata<'_>) -> bool {
+        self.verbose_level
+            .is_none_or(|verbose_level| *metadata.level() >= verbose_level)
+    }
 
-        fn make_writer(&'a self) -> Self::Writer {
-            MockWriter::new(self.buf.clone())
+    /// The level written in the `level` field, which can be overridden per
+    /// target. The closest matching parent module wins.
+    fn label_level(&self, metadata: &Metadata<'_>) -> tracing::Level {
+        let mut target = metadata.target();
+        if !self.target_level_overrides.is_empty() {
+            loop {
+                if let Some(level) = self.target_level_overrides.get(target) {
+                    return *level;
+                }
+                match target.rfind("::") {
+                    Some(index) => target = &target[..index],
+                    None => break,
+                }
+            }
         }
+        *metadata.level()
     }
 
-    fn subscriber() -> SubscriberBuilder<FieldsFormatter, EventsFormatter> {
-        builder::builder().subscriber_builder()
-    }
+    fn serialize_builtin<S, W>(
+        &self,
+        field: BuiltinField,
+        serializer: &mut Serializer<'_, W>,
+        metadata: &Metadata<'_>,
+        span: Option<&registry::SpanRef<'_, S>>,
+        event_time: Option<time::OffsetDateTime>,
+        span_lifecycle: Option<&'static str>,
+    ) -> Result<(), SerializerError>
+    where
+        S: for<'a> LookupSpan<'a>,
+        W: fmt::Write,
+    {
+        match field {
+            BuiltinField::Ts if self.writes_timestamp() => {
+                // a single `now` keeps all the timestamps consistent
+                let now = event_time.unwrap_or_else(|| self.clock.now());
+                if self.with_timestamp {
+                    serialize_rfc3339(serializer, "ts", now)?;
+                }
+                if let Some(key) = &self.timestamp_nanos_field {
+                    serializer.serialize_entry_no_quote(key, now.unix_timestamp_nanos())?;
+                }
+                for (key, format) in &self.additional_timestamps {
+                    let nanos = now.unix_timestamp_nanos();
+                    match format {
+                        TimestampFormat::Rfc3339 => serialize_rfc3339(serializer, key, now)?,
+                        TimestampFormat::UnixSeconds => {
+                            serializer.serialize_entry_no_quote(key, now.unix_timestamp())?;
+                        }
+                        TimestampFormat::UnixMillis => {
+                            serializer
+                                .serialize_entry_no_quote(key, nanos.div_euclid(1_000_000))?;
+                        }
+                        TimestampFormat::UnixMicros => {
+                            serializer.serialize_entry_no_quote(key, nanos.div_euclid(1_000))?;
+                        }
+                        TimestampFormat::UnixNanos => {
+                            serializer.serialize_entry_no_quote(key, nanos)?;
+                        }
+                    }
+                }
+            }
+            BuiltinField::Seq if self.with_sequence_numbers => {
+                let seq = self.sequence.fetch_add(1, Ordering::Relaxed);
+                serializer.serialize_entry_no_quote("seq", seq)?;
+            }
+            BuiltinField::Uptime if self.with_uptime => {
+                let uptime = self.start.elapsed().as_millis();
+                serializer.serialize_entry_no_quote("uptime_ms", uptime)?;
+            }
+            #[cfg(feature = "event_id")]
+            BuiltinField::EventId if self.with_event_id => {
+                let mut buffer = uuid::Uuid::encode_buffer();
+                let id = uuid::Uuid::new_v4().hyphenated().encode_lower(&mut buffer);
+                serializer.serialize_entry("event_id", id)?;
+            }
+            BuiltinField::Level
+                if self.with_level
+                    || self.with_level_and_severity
+                    || self.verbosity_field.is_some() =>
+            {
+                let label_level = self.label_level(metadata);
+                let level = match (label_level, self.with_short_level) {
+                    (tracing::Level::ERROR, false) => "error",
+                    (tracing::Level::WARN, false) => "warn",
+                    (tracing::Level::INFO, false) => "info",
+                    (tracing::Level::DEBUG, false) => "debug",
+                    (tracing::Level::TRACE, false) => "trace",
+                    (tracing::Level::ERROR, true) => "E",
+                    (tracing::Level::WARN, true) => "W",
+                    (tracing::Level::INFO, true) => "I",
+                    (tracing::Level::DEBUG, true) => "D",
+                    (tracing::Level::TRACE, true) => "T",
+                };
 
-    #[test]
-    #[cfg(not(feature = "ansi_logs"))]
-    fn test_span_and_span_path_with_quoting() {
-        use tracing::subscriber;
+                #[cfg(feature = "ansi_logs")]
+                {
+                    if self.with_ansi_color && !serializer.is_json() {
+                        let level_str = self
+                            .level_colors
+                            .get(label_level)
+                            .to_nu_ansi_term()
+                            .bold()
+                            .paint(level);
 
-        let mock_writer = MockMakeWriter::new();
-        let subscriber = subscriber().with_writer(mock_writer.clone()).finish();
+                        serializer
+                            .serialize_level_entry(&self.level_key, &level_str.to_string())?;
+                    } else {
+                        serializer.serialize_level_entry(&self.level_key, level)?;
+                    }
+                }
 
-        subscriber::with_default(subscriber, || {
-            let _top = info_span!("top").entered();
-            // the ' ' requires quoting
-            let _middle = info_span!("mid dle").entered();
-            let _bottom = info_span!("bottom").entered();
+                #[cfg(not(feature = "ansi_logs"))]
+                serializer.serialize_level_entry(&self.level_key, level)?;
 
-            tracing::info!("message");
-        });
+                if self.with_level_and_severity {
+                    serializer
+                        .serialize_entry_no_quote("severity", syslog_severity(metadata.level()))?;
+                }
+                if let Some(key) = &self.verbosity_field {
+                    serializer.serialize_entry_no_quote(key, verbosity(metadata.level()))?;
+                }
 
-        let content = mock_writer.get_content();
+                for (key, format_level) in &self.extra_level_fields {
+                    serializer.serialize_entry(key, &format_level(*metadata.level()))?;
+                }
+            }
+            BuiltinField::Kind => {
+                if self.with_kind {
+                    // span lifecycle events from `FmtSpan` use the metadata of the span
+                    let kind = if metadata.is_span() { "span" } else { "event" };
+                    serializer.serialize_entry("kind", kind)?;
+                }
+                if let Some(span_lifecycle) = span_lifecycle {
+                    serializer.serialize_entry("span_lifecycle", span_lifecycle)?;
+                }
+            }
+            BuiltinField::EventName if self.with_event_name => {
+                serializer.serialize_entry("name", metadata.name())?;
+            }
+            BuiltinField::Target if self.with_target && self.is_verbose(metadata) => {
+                let always_quote = self.with_quoted_target || self.with_quoted_metadata;
+                serialize_metadata_entry(serializer, "target", metadata.target(), always_quote)?;
+            }
+            BuiltinField::Crate if self.with_crate_name => {
+                let target = metadata.target();
+                let name = target.split_once("::").map_or(target, |(name, _)| name);
+                if !name.is_empty() {
+                    serialize_metadata_entry(serializer, "crate", name, self.with_quoted_metadata)?;
+                }
+            }
+            BuiltinField::Location if self.with_location && self.is_verbose(metadata) => {
+                if let (Some(file), Some(line)) = (metadata.file(), metadata.line()) {
+                    serializer.serialize_entry("location", &format!("{}:{}", file, line))?;
+                }
+            }
+            BuiltinField::Caller if self.with_caller && self.is_verbose(metadata) => {
+                if let (Some(file), Some(line)) = (metadata.file(), metadata.line()) {
+                    serializer.serialize_entry("caller", &format!("{}:{}", file, line))?;
+                }
+            }
+            BuiltinField::ModulePath if self.with_module_path => {
+                if let Some(module) = metadata.module_path() {
+                    serialize_metadata_entry(
+                        serializer,
+                        "module_path",
+                        module,
+                        self.with_quoted_metadata,
+                    )?;
+                }
+            }
+            BuiltinField::Span if self.with_span_name => {
+                if let Some(span) = span {
+                    let root;
+                    let span = match self.span_ref {
+                        SpanRef::Leaf => span,
+                        SpanRef::Root => {
+                            root = span.scope().from_root().next();
+                            root.as_ref().unwrap_or(span)
+                        }
+                    };
+
+                    if self.with_span_name_id_combined {
+                        let name_id = format!(
+                            "{}{}{}",
+                            span.name(),
+                            self.span_name_id_joiner,
+                            span.id().into_u64()
+                        );
+                        serialize_metadata_entry(
+                            serializer,
+                            "span",
+                            &name_id,
+                            self.with_quoted_metadata,
+                        )?;
+                    } else {
+                        serialize_metadata_entry(
+                            serializer,
+                            "span",
+                            span.name(),
+                            self.with_quoted_metadata,
+                        )?;
+                    }
+                }
+            }
+            BuiltinField::SpanPath if self.with_span_path && self.is_verbose(metadata) => {
+                if let Some(span) = span {
+                    let always_quote = self.with_quoted_span_path || self.with_quoted_metadata;
+                    if self.with_span_path_ids {
+                        serialize_span_path_ids(serializer, span, always_quote)?;
+                    } else {
+                        serialize_span_path(serializer, span, always_quote)?;
+                    }
+                }
+            }
+            #[cfg(feature = "otel")]
+            BuiltinField::OtelIds if self.with_otel_ids => {
+                if let Some(span) = span {
+                    serialize_otel_ids(serializer, span)?;
+                }
+            }
+            BuiltinField::Static => {
+                if let Some(version) = &self.version {
+                    serializer.serialize_entry("version", version)?;
+                }
+                if let Some(git_sha) = &self.git_sha {
+                    serializer.serialize_entry("git_sha", git_sha)?;
+                }
+                for (key, value) in &self.static_fields {
+                    serializer.serialize_entry(key, value)?;
+                }
+                for dynamic_fields in &self.dynamic_fields {
+                    for (key, value) in dynamic_fields() {
+                        // the keys can't be checked up front, so a field
+                        // with an invalid key is skipped instead
+                        match serializer.serialize_entry(&key, &value) {
+                            Err(SerializerError::InvalidKey) => {}
+                            result => result?,
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl EventsFormatter {
+    /// Writes the message first on the line without a key, for
+    /// [`Builder::with_bare_message`](crate::Builder::with_bare_message).
+    fn serialize_bare_message<W: fmt::Write>(
+        &self,
+        serializer: &mut Serializer<'_, W>,
+        event: &Event<'_>,
+    ) -> Result<(), SerializerError> {
+        let field_options = &self.field_options;
+        if field_options.key("message").is_none() {
+            return Ok(());
+        }
+
+        let mut visitor = MessageVisitor(None);
+        event.record(&mut visitor);
+        let message = visitor
+            .0
+            .filter(|message| !(self.with_skip_blank_message && message.trim().is_empty()))
+            .or_else(|| self.default_message.clone());
+
+        if let Some(message) = message {
+            let message = if field_options.trim_values {
+                message.trim_ascii()
+            } else {
+                &message
+            };
+            if let Some(message) = field_options.transform("message", message) {
+                serializer.serialize_bare_value(field_options.truncate(&message))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the formatted fields of a span as a single value with the name of
+/// the span as key, quoted for logfmt and as an object for JSON.
+fn serialize_span_fields_grouped<W: fmt::Write>(
+    serializer: &mut Serializer<'_, W>,
+    name: &str,
+    fields: &str,
+) -> Result<(), SerializerError> {
+    if serializer.is_json() {
+        serializer.serialize_key_and_separator(name)?;
+        serializer.writer.write_char('{')?;
+        serializer.writer.write_str(fields)?;
+        serializer.writer.write_char('}')?;
+        Ok(())
+    } else {
+        serializer.serialize_entry(name, fields)
+    }
+}
+
+/// Writes `time` as an RFC 3339 timestamp.
+fn serialize_rfc3339<W: fmt::Write>(
+    serializer: &mut Serializer<'_, W>,
+    key: &str,
+    time: time::OffsetDateTime,
+) -> Result<(), SerializerError> {
+    serializer.serialize_key_and_separator(key)?;
+    // timestamps never need quoting, but JSON needs a string
+    let json = serializer.is_json();
+    if json {
+        serializer.writer.write_char('"')?;
+    }
+    time.format_into(
+        &mut *serializer,
+        &time::format_description::well_known::Rfc3339,
+    )
+    .map_err(|_e| fmt::Error)?;
+    if json {
+        serializer.writer.write_char('"')?;
+    }
+    Ok(())
+}
+
+/// Writes a value taken from the metadata, quoted as needed like any other
+/// value unless `always_quote` is set.
+fn serialize_metadata_entry<W: fmt::Write>(
+    serializer: &mut Serializer<'_, W>,
+    key: &str,
+    value: &str,
+    always_quote: bool,
+) -> Result<(), SerializerError> {
+    serializer.serialize_key_and_separator(key)?;
+    if always_quote {
+        serializer.serialize_value_quoted(value)
+    } else {
+        serializer.serialize_value(value)
+    }
+}
+
+/// Like `serialize_span_path`, but with the span ids instead of the names.
+fn serialize_span_path_ids<S, W>(
+    serializer: &mut Serializer<'_, W>,
+    span: &registry::SpanRef<'_, S>,
+    always_quote: bool,
+) -> Result<(), SerializerError>
+where
+    S: for<'a> LookupSpan<'a>,
+    W: fmt::Write,
+{
+    serializer.serialize_key_and_separator("span_path")?;
+
+    // ids never need to be quoted, but JSON needs a string
+    let always_quote = always_quote || serializer.is_json();
+    if always_quote {
+        serializer.writer.write_char(serializer.quote_char())?;
+    }
+    let mut insert_sep = false;
+    for span in span.scope().from_root() {
+        if insert_sep {
+            serializer.writer.write_char('>')?;
+        }
+        write!(serializer.writer, "{}", span.id().into_u64())?;
+        insert_sep = true;
+    }
+    if always_quote {
+        serializer.writer.write_char(serializer.quote_char())?;
+    }
+
+    Ok(())
+}
+
+fn serialize_span_path<S, W>(
+    serializer: &mut Serializer<'_, W>,
+    span: &registry::SpanRef<'_, S>,
+    always_quote: bool,
+) -> Result<(), SerializerError>
+where
+    S: for<'a> LookupSpan<'a>,
+    W: fmt::Write,
+{
+    serializer.serialize_key_and_separator("span_path")?;
+    write_span_path(
+        serializer,
+        || span.scope().from_root().map(|span| span.name()),
+        always_quote,
+    )
+}
+
+/// Writes the span names joined with `>`.
+fn write_span_path<'n, W, I>(
+    serializer: &mut Serializer<'_, W>,
+    names: impl Fn() -> I,
+    always_quote: bool,
+) -> Result<(), SerializerError>
+where
+    W: fmt::Write,
+    I: Iterator<Item = &'n str>,
+{
+    // if none of the span names need to be quoted, or escaped once quoted,
+    // we can do things a bit faster
+    let is_plain =
+        |ch| !serializer.value_needs_quote(ch) && (!always_quote || serializer.is_plain(ch));
+    if names().all(|name| name.chars().all(is_plain)) {
+        write_plain_span_path(serializer, names(), always_quote)
+    } else {
+        write_escaped_span_path(serializer, names(), always_quote)
+    }
+}
+
+/// Writes span names that need no escaping directly to the writer.
+fn write_plain_span_path<'n, W>(
+    serializer: &mut Serializer<'_, W>,
+    names: impl Iterator<Item = &'n str>,
+    always_quote: bool,
+) -> Result<(), SerializerError>
+where
+    W: fmt::Write,
+{
+    if always_quote {
+        serializer.writer.write_char(serializer.quote_char())?;
+    }
+    let mut insert_sep = false;
+    for name in names {
+        if insert_sep {
+            serializer.writer.write_char('>')?;
+        }
+        serializer.writer.write_str(name)?;
+        insert_sep = true;
+    }
+    if always_quote {
+        serializer.writer.write_char(serializer.quote_char())?;
+    }
+    Ok(())
+}
+
+/// Joins the span names first, to quote and escape them as one value.
+fn write_escaped_span_path<'n, W>(
+    serializer: &mut Serializer<'_, W>,
+    names: impl Iterator<Item = &'n str>,
+    always_quote: bool,
+) -> Result<(), SerializerError>
+where
+    W: fmt::Write,
+{
+    // not sized up front, which would take another pass over the spans
+    let mut span_path = String::new();
+    let mut insert_sep = false;
+    for name in names {
+        if insert_sep {
+            span_path.push('>');
+        }
+        span_path.push_str(name);
+        insert_sep = true;
+    }
+    if always_quote {
+        serializer.serialize_value_quoted(&span_path)
+    } else {
+        serializer.serialize_value(&span_path)
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for EventsFormatter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut serializer = Serializer::new(
+            &mut writer,
+            &self.serializer,
+            #[cfg(feature = "ansi_logs")]
+            self.with_ansi_color,
+        );
+
+        let mut visit = || {
+            let metadata = event.metadata();
+            serializer.begin_line()?;
+
+            // `None` for events with `parent: None`, even inside a span
+            let span = if self.needs_span() {
+                ctx.parent_span()
+            } else {
+                None
+            };
+
+            let event_time = match &self.timestamp_from_field {
+                Some(field) if self.writes_timestamp() => {
+                    let mut visitor = TimestampVisitor {
+                        field,
+                        timestamp: None,
+                    };
+                    event.record(&mut visitor);
+                    visitor.timestamp
+                }
+                _ => None,
+            };
+
+            let span_lifecycle = if metadata.is_span() {
+                let mut visitor = SpanLifecycleVisitor(None);
+                event.record(&mut visitor);
+                visitor.0
+            } else {
+                None
+            };
+
+            // there is no such thing as a bare value in JSON
+            let bare_message = self.with_fields && self.with_bare_message && !serializer.is_json();
+            if bare_message {
+                self.serialize_bare_message(&mut serializer, event)?;
+            }
+
+            if self.metadata_position == MetadataPosition::Prefix {
+                for field in &self.field_order {
+                    self.serialize_builtin(
+                        *field,
+                        &mut serializer,
+                        metadata,
+                        span.as_ref(),
+                        event_time,
+                        span_lifecycle,
+                    )?;
+                }
+            }
+
+            if !self.promoted_span_fields.is_empty() {
+                if let Some(leaf_span) = ctx.parent_span() {
+                    serialize_promoted_span_fields::<_, N, _>(
+                        &mut serializer,
+                        &leaf_span,
+                        &self.promoted_span_fields,
+                    )?;
+                }
+            }
+
+            let mut visitor = Visitor::new(&mut serializer, &self.field_options);
+            if self.with_duration_suffix_parsing {
+                visitor.duration_unit = Some(self.duration_unit);
+            }
+            if metadata.is_span() {
+                visitor.span_duration_format = self.span_duration_format;
+            }
+            visitor.set_duplicate_policy(self.duplicate_policy);
+            if self.with_field_grouping {
+                visitor.set_field_grouping();
+            }
+            if let Some(field_sort) = &self.field_sort {
+                visitor.set_field_sort(field_sort);
+            }
+            visitor.skip_blank_message = self.with_skip_blank_message;
+            visitor.skip_message = bare_message;
+            if self.with_root_span_fields {
+                visitor.keys = Some(Vec::new());
+            }
+            visitor.remaining_fields = self.max_fields;
+            if event_time.is_some() {
+                // already written as the `ts`
+                visitor.skipped_field = self.timestamp_from_field.as_deref();
+            }
+            if self.with_fields {
+                event.record(&mut visitor);
+                if let Some(default_message) = &self.default_message {
+                    if !visitor.saw_message && !bare_message {
+                        visitor.write_entry("message", default_message);
+                    }
+                }
+            }
+            let event_keys = visitor.keys.take().unwrap_or_default();
+            let mut remaining_fields = visitor.remaining_fields;
+            let mut fields_truncated = visitor.fields_truncated;
+            let dropped_fields = visitor.dropped_fields;
+            visitor.finish()?;
+
+            if self.with_root_span_fields {
+                if let Some(root) = ctx
+                    .parent_span()
+                    .and_then(|span| span.scope().from_root().next())
+                {
+                    serialize_root_span_fields::<_, N, _>(&mut serializer, &root, &event_keys)?;
+                }
+            }
+
+            // Write all fields from spans
+            let mut span_field_count = 0;
+            if let Some(leaf_span) = ctx.parent_span() {
+                for span in leaf_span.scope().from_root() {
+                    let skipped = match self
+                        .with_span_fields_once
+                        .then(|| self.span_fields_written(&span))
+                    {
+                        Some(Some(WrittenFields::All)) => continue,
+                        Some(Some(WrittenFields::First(count))) => count,
+                        _ => 0,
+                    };
+
+                    // the fields can be missing if another layer manages the
+                    // span's extensions, which only leaves them out
+                    let written = match span.extensions().get::<FormattedFields<N>>() {
+                        Some(data) if !data.is_empty() => {
+                            // the fields an earlier event already wrote are left out
+                            let mut entries = serializer.formatted_entries(data);
+                            if skipped > 0 {
+                                entries.nth(skipped - 1);
+                            }
+                            match self.serialize_span_fields(
+                                &mut serializer,
+                                span.name(),
+                                entries.rest(),
+                                &mut remaining_fields,
+                                &mut span_field_count,
+                            )? {
+                                WrittenFields::First(count) => {
+                                    WrittenFields::First(skipped + count)
+                                }
+                                WrittenFields::All => WrittenFields::All,
+                            }
+                        }
+                        _ => WrittenFields::All,
+                    };
+                    fields_truncated |= written != WrittenFields::All;
+
+                    // fields cut off by the limit are written with the next event
+                    if self.with_span_fields_once {
+                        let mut extensions = span.extensions_mut();
+                        if let Some(marker) = extensions.get_mut::<SpanFieldsWritten>() {
+                            marker.set(self.instance_id, written);
+                        } else {
+                            extensions.insert(SpanFieldsWritten(vec![(self.instance_id, written)]));
+                        }
+                    }
+                }
+            }
+
+            if self.with_span_field_count {
+                serializer.serialize_entry_no_quote("span_fields", span_field_count)?;
+            }
+            if fields_truncated {
+                serializer.serialize_entry_no_quote("fields_truncated", true)?;
+            }
+            if self.with_filtered_count && dropped_fields > 0 {
+                serializer.serialize_entry_no_quote("fields_dropped", dropped_fields)?;
+            }
+
+            if self.metadata_position == MetadataPosition::Suffix {
+                for field in &self.field_order {
+                    self.serialize_builtin(
+                        *field,
+                        &mut serializer,
+                        metadata,
+                        span.as_ref(),
+                        event_time,
+                        span_lifecycle,
+                    )?;
+                }
+            }
+
+            serializer.end_line()
+        };
+
+        visit().map_err(|_e: SerializerError| fmt::Error)?;
+
+        if let Some(suffix) = &self.line_suffix {
+            writer.write_str(suffix)?;
+        }
+        if self.with_trailing_newline {
+            writer.write_str(self.line_ending.as_str())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the fields of the root span again with a `root.` prefix, leaving out
+/// any that clash with an event field.
+fn serialize_root_span_fields<S, N, W>(
+    serializer: &mut Serializer<'_, W>,
+    root: &registry::SpanRef<'_, S>,
+    event_keys: &[String],
+) -> Result<(), SerializerError>
+where
+    S: for<'a> LookupSpan<'a>,
+    N: 'static,
+    W: fmt::Write,
+{
+    let ext = root.extensions();
+    let Some(data) = ext.get::<FormattedFields<N>>() else {
+        return Ok(());
+    };
+
+    let quote_char = serializer.quote_char();
+    for (key, value) in serializer.formatted_entries(data) {
+        // keep quoted keys quoted, with the prefix inside the quotes
+        let (quote, key) = match key.strip_prefix(quote_char) {
+            Some(key) => (Some(quote_char), key),
+            None => (None, key),
+        };
+        let clashes = event_keys.iter().any(|event_key| {
+            event_key
+                .strip_prefix("root.")
+                .is_some_and(|event_key| key.trim_end_matches(quote_char) == event_key)
+        });
+        if !clashes {
+            let quote = quote.map_or_else(String::new, String::from);
+            serializer.serialize_formatted_pair(format_args!("{}root.{}", quote, key), value)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the value of each promoted field from the closest span, starting at
+/// `leaf`, that recorded it.
+fn serialize_promoted_span_fields<S, N, W>(
+    serializer: &mut Serializer<'_, W>,
+    leaf: &registry::SpanRef<'_, S>,
+    promoted: &[(String, String)],
+) -> Result<(), SerializerError>
+where
+    S: for<'a> LookupSpan<'a>,
+    N: 'static,
+    W: fmt::Write,
+{
+    let quote_char = serializer.quote_char();
+    for (field_name, output_key) in promoted {
+        let value = leaf.scope().find_map(|span| {
+            let ext = span.extensions();
+            let data = ext.get::<FormattedFields<N>>()?;
+            serializer
+                .formatted_entries(data)
+                .find(|(key, _)| key.trim_matches(quote_char) == field_name)
+                .map(|(_, value)| value.to_owned())
+        });
+        if let Some(value) = value {
+            serializer.serialize_entry_formatted(output_key, &value)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the ids that `tracing-opentelemetry` assigned to the span, if any.
+#[cfg(feature = "otel")]
+fn serialize_otel_ids<S, W>(
+    serializer: &mut Serializer<'_, W>,
+    span: &registry::SpanRef<'_, S>,
+) -> Result<(), SerializerError>
+where
+    S: for<'a> LookupSpan<'a>,
+    W: fmt::Write,
+{
+    let ext = span.extensions();
+    let Some(otel_data) = ext.get::<tracing_opentelemetry::OtelData>() else {
+        return Ok(());
+    };
+
+    if let Some(trace_id) = otel_data
+        .trace_id()
+        .filter(|&trace_id| trace_id != opentelemetry::TraceId::INVALID)
+    {
+        serializer.serialize_entry_no_quote("trace_id", format_args!("{}", trace_id))?;
+    }
+    if let Some(span_id) = otel_data
+        .span_id()
+        .filter(|&span_id| span_id != opentelemetry::SpanId::INVALID)
+    {
+        serializer.serialize_entry_no_quote("span_id", format_args!("{}", span_id))?;
+    }
+
+    Ok(())
+}
+
+/// A new [`EventsFormatter::instance_id`].
+pub(crate) fn next_instance_id() -> u64 {
+    static NEXT_INSTANCE_ID: AtomicU64 = AtomicU64::new(0);
+    NEXT_INSTANCE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Marks spans whose fields have already been written, used by
+/// [`Builder::with_span_fields_once`](crate::Builder::with_span_fields_once).
+/// Holds the [`EventsFormatter::instance_id`] of every formatter that wrote
+/// them and how many it wrote, since the registry and so the span are shared
+/// by all layers.
+struct SpanFieldsWritten(Vec<(u64, WrittenFields)>);
+
+impl SpanFieldsWritten {
+    fn set(&mut self, instance_id: u64, fields: WrittenFields) {
+        match self.0.iter_mut().find(|(id, _)| *id == instance_id) {
+            Some((_, written)) => *written = fields,
+            None => self.0.push((instance_id, fields)),
+        }
+    }
+}
+
+/// How many of the fields of a span were written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WrittenFields {
+    All,
+    /// The first fields, before the limit of
+    /// [`Builder::with_max_fields`](crate::Builder::with_max_fields) was
+    /// reached.
+    First(usize),
+}
+
+/// A formatter that formats span fields into logfmt.
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub struct FieldsFormatter {
+    pub(crate) field_options: FieldOptions,
+    pub(crate) serializer: SerializerOptions,
+    pub(crate) with_leading_separator: bool,
+}
+
+impl FieldsFormatter {
+    /// Write a separator before the first field, e.g. ` a=1 b=2` instead of
+    /// `a=1 b=2`, when appending the fields to other output. Nothing is
+    /// written if there are no fields. Defaults to `false`.
+    ///
+    /// Fields recorded on a span later are always appended with a separator,
+    /// and the separator in front of span fields is left out when they are
+    /// written with an event, so this only matters when the formatter is used
+    /// on its own.
+    pub fn with_leading_separator(mut self, enable: bool) -> Self {
+        self.with_leading_separator = enable;
+        self
+    }
+
+    fn format_fields_with<R: RecordFields>(
+        &self,
+        mut writer: format::Writer<'_>,
+        fields: R,
+        leading_separator: bool,
+    ) -> fmt::Result {
+        let mut serializer = Serializer::new(
+            &mut writer,
+            &self.serializer,
+            #[cfg(feature = "ansi_logs")]
+            false,
+        );
+        if leading_separator {
+            serializer.continue_line();
+        }
+        let mut visitor = Visitor::new(&mut serializer, &self.field_options);
+        fields.record(&mut visitor);
+        let _ = visitor.finish();
+        Ok(())
+    }
+}
+
+impl<'writer> FormatFields<'writer> for FieldsFormatter {
+    fn format_fields<R: RecordFields>(
+        &self,
+        writer: format::Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        self.format_fields_with(writer, fields, self.with_leading_separator)
+    }
+
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &tracing_core::span::Record<'_>,
+    ) -> fmt::Result {
+        // the default separates the new fields with a space, which is wrong
+        // for JSON, and doubled with a leading separator
+        let leading_separator = self.with_leading_separator || !current.fields.is_empty();
+        self.format_fields_with(current.as_writer(), fields, leading_separator)
+    }
+}
+
+/// Finds the timestamp in the field set with
+/// [`Builder::with_timestamp_from_field`](crate::Builder::with_timestamp_from_field),
+/// if it is a valid RFC 3339 timestamp.
+struct TimestampVisitor<'a> {
+    field: &'a str,
+    timestamp: Option<time::OffsetDateTime>,
+}
+
+impl TimestampVisitor<'_> {
+    fn parse(&mut self, value: &str) {
+        self.timestamp =
+            time::OffsetDateTime::parse(value, &time::format_description::well_known::Rfc3339).ok();
+    }
+}
+
+impl Visit for TimestampVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == self.field {
+            self.parse(value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        // e.g. a timestamp recorded with `%`
+        if field.name() == self.field {
+            self.parse(&format!("{:?}", value));
+        }
+    }
+}
+
+/// Finds which span lifecycle event from `FmtSpan` an event is, from its
+/// message.
+struct SpanLifecycleVisitor(Option<&'static str>);
+
+impl Visit for SpanLifecycleVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = match value {
+                "new" => Some("new"),
+                "enter" => Some("enter"),
+                "exit" => Some("exit"),
+                "close" => Some("close"),
+                _ => None,
+            };
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.record_str(field, &format!("{:?}", value));
+        }
+    }
+}
+
+/// Finds the message of an event.
+struct MessageVisitor(Option<String>);
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
+
+/// Settings for the recorded fields, shared by the event and span formatters.
+#[derive(Clone)]
+pub(crate) struct FieldOptions {
+    pub(crate) renames: HashMap<String, String>,
+    pub(crate) denylist: HashSet<String>,
+    pub(crate) max_value_length: Option<usize>,
+    pub(crate) with_error_field: bool,
+    pub(crate) quote_numbers: bool,
+    pub(crate) skip_none_debug: bool,
+    pub(crate) type_hints: bool,
+    pub(crate) pretty_debug: bool,
+    pub(crate) trim_values: bool,
+    pub(crate) fallback_key: Option<String>,
+    pub(crate) value_transform: Option<ValueTransform>,
+    pub(crate) bytes_encoding: BytesEncoding,
+    pub(crate) dotted_fields: bool,
+}
+
+impl Default for FieldOptions {
+    fn default() -> Self {
+        Self {
+            renames: HashMap::new(),
+            denylist: HashSet::new(),
+            max_value_length: None,
+            with_error_field: false,
+            quote_numbers: false,
+            skip_none_debug: false,
+            type_hints: false,
+            pretty_debug: false,
+            trim_values: false,
+            fallback_key: None,
+            value_transform: None,
+            bytes_encoding: BytesEncoding::default(),
+            dotted_fields: true,
+        }
+    }
+}
+
+impl FieldOptions {
+    /// The key a field is written with, or `None` if it should be left out.
+    fn key<'k>(&'k self, key: &'k str) -> Option<&'k str> {
+        if self.denylist.contains(key) {
+            return None;
+        }
+        Some(self.renames.get(key).map_or(key, String::as_str))
+    }
+
+    /// The value a field is written with, or `None` if it should be left out.
+    fn transform<'v>(&self, key: &str, value: &'v str) -> Option<Cow<'v, str>> {
+        match &self.value_transform {
+            Some(value_transform) => value_transform(key, value),
+            None => Some(Cow::Borrowed(value)),
+        }
+    }
+
+    fn truncate<'v>(&self, value: &'v str) -> &'v str {
+        match self
+            .max_value_length
+            .and_then(|max_value_length| value.char_indices().nth(max_value_length))
+        {
+            Some((index, _)) => &value[..index],
+            None => value,
+        }
+    }
+}
+
+/// Buffers larger than this are not kept around after an event, so that a
+/// single huge value doesn't pin the memory for the lifetime of the thread.
+const MAX_SCRATCH_CAPACITY: usize = 4096;
+
+thread_local! {
+    /// Reused by every [`Visitor`] on this thread for formatting debug values,
+    /// so that the hot path doesn't allocate a fresh `String` per event.
+    static DEBUG_FMT_BUFFER: Cell<String> = const { Cell::new(String::new()) };
+}
+
+struct Visitor<'a, 'o, W> {
+    serializer: &'a mut Serializer<'o, W>,
+    field_options: &'a FieldOptions,
+    state: Result<(), SerializerError>,
+    debug_fmt_buffer: String,
+    /// When set, debug values that look like a `Duration` are re-emitted as
+    /// a number in this unit.
+    duration_unit: Option<DurationUnit>,
+    span_duration_format: Option<DurationFormat>,
+    duplicate_policy: DuplicatePolicy,
+    /// Leave out the `message` field if it is empty or only whitespace.
+    skip_blank_message: bool,
+    /// Leave out the `message` field, which has already been written.
+    skip_message: bool,
+    /// Fields are collected here instead of being written directly when the
+    /// duplicate policy needs to see all of them first.
+    buffered: Option<Vec<BufferedField>>,
+    saw_message: bool,
+    saw_invalid_key: bool,
+    /// The keys of the recorded fields, only collected when needed.
+    keys: Option<Vec<String>>,
+    /// The number of keys replaced by the fallback key so far.
+    fallback_keys: usize,
+    /// Write the message first and errors last, see [`FieldGroup`].
+    field_grouping: bool,
+    /// Orders the fields by key, after the grouping.
+    field_sort: Option<&'a FieldSort>,
+    /// Set while a field passed to `record_error` is being written.
+    recording_error: bool,
+    /// How many more fields may be written, if limited.
+    remaining_fields: Option<usize>,
+    /// Set when a field was left out because of the limit.
+    fields_truncated: bool,
+    /// A field that is left out because it was already written.
+    skipped_field: Option<&'a str>,
+    /// Set while a field of a known type is being written, see
+    /// [`Builder::with_type_hints`](crate::Builder::with_type_hints).
+    type_hint: Option<&'static str>,
+    /// The number of fields left out by the denylist or the value transform.
+    dropped_fields: usize,
+}
+
+struct BufferedField {
+    key: String,
+    value: String,
+    quote: bool,
+    group: FieldGroup,
+}
+
+/// The order fields are written in when field grouping is enabled.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum FieldGroup {
+    Message,
+    Normal,
+    /// Fields recorded as an error, or named `error` or `error.*`.
+    Error,
+}
+
+impl<'a, 'o, W> Visitor<'a, 'o, W> {
+    fn new(serializer: &'a mut Serializer<'o, W>, field_options: &'a FieldOptions) -> Self {
+        Self {
+            serializer,
+            field_options,
+            state: Ok(()),
+            // a nested visitor (e.g. an event logged from a `Debug` impl)
+            // finds the slot empty and just starts with a new buffer
+            debug_fmt_buffer: DEBUG_FMT_BUFFER.take(),
+            duration_unit: None,
+            span_duration_format: None,
+            duplicate_policy: DuplicatePolicy::KeepAll,
+            skip_blank_message: false,
+            skip_message: false,
+            buffered: None,
+            saw_message: false,
+            saw_invalid_key: false,
+            fallback_keys: 0,
+            keys: None,
+            field_grouping: false,
+            field_sort: None,
+            recording_error: false,
+            remaining_fields: None,
+            fields_truncated: false,
+            skipped_field: None,
+            type_hint: None,
+            dropped_fields: 0,
+        }
+    }
+
+    fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicate_policy = policy;
+        self.buffered = match policy {
+            DuplicatePolicy::KeepAll => None,
+            DuplicatePolicy::KeepFirst | DuplicatePolicy::KeepLast => Some(Vec::new()),
+        };
+    }
+
+    fn set_field_grouping(&mut self) {
+        self.field_grouping = true;
+        self.buffered.get_or_insert_with(Vec::new);
+    }
+
+    fn set_field_sort(&mut self, field_sort: &'a FieldSort) {
+        self.field_sort = Some(field_sort);
+        self.buffered.get_or_insert_with(Vec::new);
+    }
+
+    fn field_group(&self, key: &str) -> FieldGroup {
+        if key == "message" {
+            FieldGroup::Message
+        } else if self.recording_error || key == "error" || key.starts_with("error.") {
+            FieldGroup::Error
+        } else {
+            FieldGroup::Normal
+        }
+    }
+}
+
+impl<W> Visit for Visitor<'_, '_, W>
+where
+    W: fmt::Write,
+{
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        if self.state.is_ok() {
+            self.type_hint = Some("float");
+            self.record_debug_no_quote(field, value);
+            self.type_hint = None;
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if self.state.is_ok() {
+            self.type_hint = Some("int");
+            self.record_debug_no_quote(field, value);
+            self.type_hint = None;
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if self.state.is_ok() {
+            self.type_hint = Some("int");
+            self.record_debug_no_quote(field, value);
+            self.type_hint = None;
+        }
+    }
+
+    fn record_i128(&mut self, field: &Field, value: i128) {
+        if self.state.is_ok() {
+            self.type_hint = Some("int");
+            self.record_debug_no_quote(field, value);
+            self.type_hint = None;
+        }
+    }
+
+    fn record_u128(&mut self, field: &Field, value: u128) {
+        if self.state.is_ok() {
+            self.type_hint = Some("int");
+            self.record_debug_no_quote(field, value);
+            self.type_hint = None;
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if self.state.is_ok() {
+            self.type_hint = Some("bool");
+            self.record_debug_no_quote(field, value);
+            self.type_hint = None;
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if self.state.is_ok() {
+            self.type_hint = Some("str");
+            self.write_entry(field.name(), value);
+            self.type_hint = None;
+        }
+    }
+
+    fn record_bytes(&mut self, field: &Field, value: &[u8]) {
+        if self.state.is_ok() {
+            let encoding = self.field_options.bytes_encoding;
+            self.type_hint = Some("bytes");
+            self.record_debug_no_quote(
+                field,
+                EncodedBytes {
+                    bytes: value,
+                    encoding,
+                },
+            );
+            self.type_hint = None;
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        if self.state.is_ok() {
+            self.recording_error = true;
+            self.record_debug(field, &format_args!("{}", value));
+            self.recording_error = false;
+        }
+    }
+
+    #[cfg(all(tracing_unstable, feature = "valuable"))]
+    fn record_value(&mut self, field: &Field, value: valuable::Value<'_>) {
+        if self.state.is_ok() {
+            self.record_valuable(field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if self.state.is_ok() {
+            self.debug_fmt_buffer.clear();
+            let _ = if self.field_options.pretty_debug {
+                write!(self.debug_fmt_buffer, "{:#?}", value)
+            } else {
+                write!(self.debug_fmt_buffer, "{:?}", value)
+            };
+
+            // the message is always written, even if it reads `None`
+            if self.field_options.skip_none_debug
+                && !self.recording_error
+                && field.name() != "message"
+                && self.debug_fmt_buffer == "None"
+            {
+                return;
+            }
+
+            if let Some(format) = self.span_duration_format {
+                if matches!(field.name(), "time.busy" | "time.idle") {
+                    if let Some(nanos) = duration::parse_debug_duration(&self.debug_fmt_buffer) {
+                        self.write_span_duration(field.name(), nanos, format);
+                        return;
+                    }
+                }
+            }
+
+            // the message is kept as is, even if it looks like a duration
+            if let Some(unit) = self.duration_unit.filter(|_| field.name() != "message") {
+                if let Some(nanos) = duration::parse_debug_duration(&self.debug_fmt_buffer) {
+                    self.write_duration(field.name(), nanos, unit);
+                    return;
+                }
+            }
+
+            self.write_debug_fmt_buffer(field.name());
+        }
+    }
+}
+
+impl<'a, W> Visitor<'a, '_, W>
+where
+    W: fmt::Write,
+{
+    fn write_span_duration(&mut self, name: &str, nanos: u128, format: DurationFormat) {
+        let unit = match format {
+            DurationFormat::Nanos => DurationUnit::Nanoseconds,
+            DurationFormat::Millis => DurationUnit::Milliseconds,
+            DurationFormat::Human => {
+                self.write_entry_no_quote(name, format_args!("{}", duration::DisplayHuman(nanos)));
+                return;
+            }
+        };
+        self.write_duration(name, nanos, unit);
+    }
+
+    /// Writes a duration in `unit`, with the unit added to the resolved key
+    /// of the field, e.g. `elapsed_ms`.
+    fn write_duration(&mut self, name: &str, nanos: u128, unit: DurationUnit) {
+        self.write_entry_no_quote_with_suffix(
+            name,
+            Some(unit.suffix()),
+            format_args!("{}", duration::DisplayInUnit { nanos, unit }),
+        );
+    }
+
+    fn record_debug_no_quote(&mut self, field: &Field, value: impl fmt::Debug) {
+        if self.state.is_ok() {
+            self.write_entry_no_quote(field.name(), value);
+        }
+    }
+
+    /// The key a field is written with, or `None` if it should be left out.
+    /// The suffix is added after the key is renamed, e.g. for a unit.
+    fn resolve_key<'k>(&mut self, key: &'k str, suffix: Option<&str>) -> Option<Cow<'k, str>>
+    where
+        'a: 'k,
+    {
+        if self.skipped_field == Some(key) {
+            return None;
+        }
+        let field_options = self.field_options;
+        let Some(key) = field_options.key(key) else {
+            self.dropped_fields += 1;
+            return None;
+        };
+
+        if let Some(remaining) = &mut self.remaining_fields {
+            if *remaining == 0 {
+                self.fields_truncated = true;
+                return None;
+            }
+            *remaining -= 1;
+        }
+
+        let key = match &field_options.fallback_key {
+            Some(fallback_key) if !self.serializer.is_valid_key(key) => {
+                self.fallback_keys += 1;
+                Cow::Owned(format!("{}_{}", fallback_key, self.fallback_keys))
+            }
+            _ => Cow::Borrowed(key),
+        };
+        let key = match suffix {
+            Some(suffix) => Cow::Owned(format!("{}_{}", key, suffix)),
+            None => key,
+        };
+
+        match self.type_hint {
+            // the message keeps its key, whatever its type
+            Some(type_hint) if field_options.type_hints && key != "message" => {
+                Some(Cow::Owned(format!("{}:{}", key, type_hint)))
+            }
+            _ => Some(key),
+        }
+    }
+
+    fn write_entry(&mut self, key: &str, value: &str) {
+        if self.skip_message && key == "message" {
+            return;
+        }
+        let value = if self.field_options.trim_values {
+            value.trim_ascii()
+        } else {
+            value
+        };
+        if let Some(value) = self.field_options.transform(key, value) {
+            self.write_transformed_entry(key, None, &value);
+        } else {
+            self.dropped_fields += 1;
+        }
+    }
+
+    fn write_transformed_entry(&mut self, key: &str, suffix: Option<&str>, value: &str) {
+        if key == "message" {
+            if self.skip_blank_message && value.trim().is_empty() {
+                return;
+            }
+            self.saw_message = true;
+        }
+        let group = self.field_group(key);
+        let Some(key) = self.resolve_key(key, suffix) else {
+            return;
+        };
+        if let Some(keys) = &mut self.keys {
+            keys.push(key.to_string());
+        }
+        let value = self.field_options.truncate(value);
+
+        if let Some(buffered) = &mut self.buffered {
+            buffered.push(BufferedField {
+                key: key.into_owned(),
+                value: value.to_owned(),
+                quote: true,
+                group,
+            });
+        } else {
+            let result = self.serializer.serialize_entry(&key, value);
+            self.record_result(result);
+        }
+    }
+
+    fn write_entry_no_quote(&mut self, key: &str, value: impl fmt::Debug) {
+        self.write_entry_no_quote_with_suffix(key, None, value);
+    }
+
+    fn write_entry_no_quote_with_suffix(
+        &mut self,
+        key: &str,
+        suffix: Option<&str>,
+        value: impl fmt::Debug,
+    ) {
+        // the value is only formatted up front if it may be replaced
+        if self.field_options.value_transform.is_some() {
+            let formatted = format!("{:?}", value);
+            match self.field_options.transform(key, &formatted) {
+                Some(Cow::Borrowed(_)) => {}
+                Some(Cow::Owned(value)) => {
+                    self.write_transformed_entry(key, suffix, &value);
+                    return;
+                }
+                None => {
+                    self.dropped_fields += 1;
+                    return;
+                }
+            }
+        }
+
+        self.saw_message |= key == "message";
+        let group = self.field_group(key);
+        let Some(key) = self.resolve_key(key, suffix) else {
+            return;
+        };
+        if let Some(keys) = &mut self.keys {
+            keys.push(key.to_string());
+        }
+
+        if let Some(buffered) = &mut self.buffered {
+            buffered.push(BufferedField {
+                key: key.into_owned(),
+                value: format!("{:?}", value),
+                quote: false,
+                group,
+            });
+        } else {
+            let result = if self.field_options.quote_numbers {
+                self.serializer
+                    .serialize_entry_quoted(&key, &format!("{:?}", value))
+            } else {
+                self.serializer.serialize_entry_no_quote(&key, value)
+            };
+            self.record_result(result);
+        }
+    }
+
+    fn write_debug_fmt_buffer(&mut self, key: &str) {
+        let value = std::mem::take(&mut self.debug_fmt_buffer);
+        self.write_entry(key, &value);
+        self.debug_fmt_buffer = value;
+    }
+
+    /// A field with an invalid key is skipped, instead of failing the whole
+    /// line. Only errors from the writer stop the recording.
+    fn record_result(&mut self, result: Result<(), SerializerError>) {
+        match result {
+            Err(SerializerError::InvalidKey) => self.saw_invalid_key = true,
+            result => self.state = result,
+        }
+    }
+
+    /// Writes any buffered fields, applying the duplicate policy and field
+    /// grouping, and the `logfmt_error` field if enabled.
+    fn finish(mut self) -> Result<(), SerializerError> {
+        if let Some(buffered) = self.buffered.take() {
+            // duplicates are resolved in the recorded order, grouping and
+            // sorting only change the order they are written in
+            let mut order: Vec<usize> = (0..buffered.len()).collect();
+            if self.field_grouping {
+                order.sort_by_key(|&index| buffered[index].group);
+            }
+            if let Some(field_sort) = self.field_sort {
+                let group = |index: usize| self.field_grouping.then_some(buffered[index].group);
+                order.sort_by(|&a, &b| {
+                    group(a)
+                        .cmp(&group(b))
+                        .then_with(|| field_sort(&buffered[a].key, &buffered[b].key))
+                });
+            }
+
+            for index in order {
+                let field = &buffered[index];
+                if self.state.is_err() {
+                    break;
+                }
+
+                let duplicate = match self.duplicate_policy {
+                    DuplicatePolicy::KeepAll => false,
+                    DuplicatePolicy::KeepFirst => {
+                        buffered[..index].iter().any(|f| f.key == field.key)
+                    }
+                    DuplicatePolicy::KeepLast => {
+                        buffered[index + 1..].iter().any(|f| f.key == field.key)
+                    }
+                };
+                if duplicate {
+                    continue;
+                }
+
+                let result = if field.quote {
+                    self.serializer.serialize_entry(&field.key, &field.value)
+                } else if self.field_options.quote_numbers {
+                    self.serializer
+                        .serialize_entry_quoted(&field.key, &field.value)
+                } else {
+                    self.serializer
+                        .serialize_entry_no_quote(&field.key, format_args!("{}", field.value))
+                };
+                self.record_result(result);
+            }
+        }
+
+        if self.saw_invalid_key && self.field_options.with_error_field && self.state.is_ok() {
+            self.state = self
+                .serializer
+                .serialize_entry("logfmt_error", "invalid_key");
+        }
+
+        std::mem::replace(&mut self.state, Ok(()))
+    }
+}
+
+impl<W> Drop for Visitor<'_, '_, W> {
+    fn drop(&mut self) {
+        if self.debug_fmt_buffer.capacity() <= MAX_SCRATCH_CAPACITY {
+            let mut buffer = std::mem::take(&mut self.debug_fmt_buffer);
+            buffer.clear();
+            DEBUG_FMT_BUFFER.set(buffer);
+        }
+    }
+}
+
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+impl<W> Visitor<'_, '_, W>
+where
+    W: fmt::Write,
+{
+    /// Writes structs as one field per struct field with dotted keys, e.g.
+    /// `user.id=5 user.name=bob`. All other compound values are written as
+    /// their debug representation.
+    fn record_valuable(&mut self, key: &str, value: valuable::Value<'_>) {
+        use valuable::{Fields, Value};
+
+        if self.state.is_err() {
+            return;
+        }
+
+        match value {
+            Value::Structable(structable)
+                if self.field_options.dotted_fields
+                    && matches!(structable.definition().fields(), Fields::Named(_)) =>
+            {
+                structable.visit(&mut ValuableFields { visitor: self, key });
+            }
+            Value::String(value) => {
+                self.write_entry(key, value);
+            }
+            Value::Bool(_)
+            | Value::F32(_)
+            | Value::F64(_)
+            | Value::I8(_)
+            | Value::I16(_)
+            | Value::I32(_)
+            | Value::I64(_)
+            | Value::I128(_)
+            | Value::Isize(_)
+            | Value::U8(_)
+            | Value::U16(_)
+            | Value::U32(_)
+            | Value::U64(_)
+            | Value::U128(_)
+            | Value::Usize(_) => {
+                self.write_entry_no_quote(key, value);
+            }
+            value => {
+                self.debug_fmt_buffer.clear();
+                let _ = match value {
+                    Value::Char(value) => write!(self.debug_fmt_buffer, "{}", value),
+                    Value::Path(value) => write!(self.debug_fmt_buffer, "{}", value.display()),
+                    Value::Error(value) => write!(self.debug_fmt_buffer, "{}", value),
+                    value => write!(self.debug_fmt_buffer, "{:?}", value),
+                };
+                self.write_debug_fmt_buffer(key);
+            }
+        }
+    }
+}
+
+/// Visits the named fields of a `valuable` struct, prefixing them with the key
+/// of the struct.
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+struct ValuableFields<'v, 'a, 'o, W> {
+    visitor: &'v mut Visitor<'a, 'o, W>,
+    key: &'v str,
+}
+
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+impl<W> valuable::Visit for ValuableFields<'_, '_, '_, W>
+where
+    W: fmt::Write,
+{
+    fn visit_value(&mut self, _value: valuable::Value<'_>) {
+        // only called for primitives and lists, which are never visited here
+    }
+
+    fn visit_named_fields(&mut self, named_values: &valuable::NamedValues<'_>) {
+        for (field, value) in named_values {
+            let key = format!("{}.{}", self.key, field.name());
+            self.visitor.record_valuable(&key, *value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::info_span;
+    use tracing_subscriber::fmt::{MakeWriter, SubscriberBuilder};
+
+    use super::*;
+
+    #[derive(Clone, Debug)]
+    struct MockWriter {
+        buf: Arc<Mutex<Vec<u8>>>,
+    }
+
+    #[derive(Clone, Debug)]
+    struct MockMakeWriter {
+        buf: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl MockMakeWriter {
+        fn new() -> Self {
+            Self {
+                buf: Arc::new(Mutex::new(Vec::new())),
+            }
+        }
+        fn get_content(&self) -> String {
+            let buf = self.buf.lock().unwrap();
+            std::str::from_utf8(&buf[..]).unwrap().to_owned()
+        }
+    }
+
+    impl MockWriter {
+        fn new(buf: Arc<Mutex<Vec<u8>>>) -> Self {
+            Self { buf }
+        }
+    }
+
+    impl io::Write for MockWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buf.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.buf.lock().unwrap().flush()
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for MockMakeWriter {
+        type Writer = MockWriter;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            MockWriter::new(self.buf.clone())
+        }
+    }
+
+    fn subscriber() -> SubscriberBuilder<FieldsFormatter, EventsFormatter> {
+        builder::builder().subscriber_builder()
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_span_and_span_path_with_quoting() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = subscriber().with_writer(mock_writer.clone()).finish();
+
+        subscriber::with_default(subscriber, || {
+            let _top = info_span!("top").entered();
+            // the ' ' requires quoting
+            let _middle = info_span!("mid dle").entered();
+            let _bottom = info_span!("bottom").entered();
+
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{:?}", content);
+        assert!(content.contains("span=bottom"));
+        assert!(content.contains("span_path=\"top>mid dle>bottom\""));
+        assert!(content.contains("info"));
+        assert!(content.contains("ts=20"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_span_and_span_path_without_quoting() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = subscriber().with_writer(mock_writer.clone()).finish();
+
+        subscriber::with_default(subscriber, || {
+            let _top = info_span!("top").entered();
+            let _middle = info_span!("middle").entered();
+            let _bottom = info_span!("bottom").entered();
+
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{:?}", content);
+        assert!(content.contains("span=bottom"));
+        assert!(content.contains("span_path=top>middle>bottom"));
+        assert!(content.contains("info"));
+        assert!(content.contains("ts=20"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_disable_span_and_span_path() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_span_name(false)
+            .with_span_path(false)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _top = info_span!("top").entered();
+            let _middle = info_span!("middle").entered();
+            let _bottom = info_span!("bottom").entered();
+
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{:?}", content);
+        assert!(!content.contains("span="));
+        assert!(!content.contains("span_path="));
+        assert!(content.contains("level=info"));
+        assert!(content.contains("ts=20"));
+    }
+
+    #[test]
+    #[cfg(feature = "ansi_logs")]
+    fn test_disable_ansi_color() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            // disable timestamp so it can be asserted
+            .with_timestamp(false)
+            .with_ansi_color(false)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        // assert that there is no ansi color sequences
+        assert_eq!(
+            content,
+            "level=info target=tracing_logfmt::formatter::tests message=message\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "ansi_logs")]
+    fn test_tee() {
+        use tracing::subscriber;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let color_writer = MockMakeWriter::new();
+        let plain_writer = MockMakeWriter::new();
+        let subscriber = tracing_subscriber::registry().with(
+            builder::builder()
+                .with_timestamp(false)
+                .tee(color_writer.clone(), plain_writer.clone()),
+        );
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!("span", a = 1).entered();
+            tracing::info!("message");
+        });
+
+        let color_content = color_writer.get_content();
+        let plain_content = plain_writer.get_content();
+
+        println!("{:?}", color_content);
+        assert!(color_content.contains(&make_ansi_key_value("span", "=span")));
+        assert_eq!(
+            plain_content,
+            "level=info target=tracing_logfmt::formatter::tests span=span span_path=span message=message a=1\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "ansi_logs")]
+    fn test_tee_sequence_and_span_fields() {
+        use tracing::subscriber;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let color_writer = MockMakeWriter::new();
+        let plain_writer = MockMakeWriter::new();
+        let subscriber = tracing_subscriber::registry().with(
+            builder::builder()
+                .with_timestamp(false)
+                .with_target(false)
+                .with_span_name(false)
+                .with_span_path(false)
+                .with_sequence_numbers(true)
+                .with_span_fields_once(true)
+                .tee(color_writer.clone(), plain_writer.clone()),
+        );
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!("span", a = 1).entered();
+            tracing::info!("first");
+            tracing::info!("second");
+        });
+
+        let color_content = color_writer.get_content();
+        let color_lines = color_content.lines().collect::<Vec<_>>();
+        assert_eq!(color_lines.len(), 2);
+        assert!(color_lines[0].contains(&make_ansi_key_value("seq", "=0")));
+        assert!(color_lines[0].ends_with(" a=1"));
+        assert!(color_lines[1].contains(&make_ansi_key_value("seq", "=1")));
+        assert!(!color_lines[1].contains("a=1"));
+        assert_eq!(
+            plain_writer.get_content(),
+            "seq=0 level=info message=first a=1\n\
+             seq=1 level=info message=second\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "ansi_logs")]
+    fn test_ansi_mode_env() {
+        let tests = vec![
+            (AnsiMode::Auto, true, vec![], true),
+            (AnsiMode::Auto, false, vec![], false),
+            (AnsiMode::Auto, true, vec![("NO_COLOR", "1")], false),
+            (AnsiMode::Auto, true, vec![("NO_COLOR", "")], true),
+            (AnsiMode::Auto, false, vec![("CLICOLOR_FORCE", "1")], true),
+            (AnsiMode::Auto, false, vec![("CLICOLOR_FORCE", "0")], false),
+            (
+                AnsiMode::Auto,
+                false,
+                vec![("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")],
+                false,
+            ),
+            (AnsiMode::Always, false, vec![("NO_COLOR", "1")], true),
+            (AnsiMode::Never, true, vec![("CLICOLOR_FORCE", "1")], false),
+        ];
+
+        for (mode, is_terminal, env, expected_output) in tests {
+            let var = |name: &str| {
+                env.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.into())
+            };
+            assert_eq!(
+                mode.resolve_with_env(|| is_terminal, var),
+                expected_output,
+                "{:?} {} {:?}",
+                mode,
+                is_terminal,
+                env
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "ansi_logs")]
+    fn test_span_and_span_path_with_quoting() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = subscriber().with_writer(mock_writer.clone()).finish();
+
+        subscriber::with_default(subscriber, || {
+            let _top = info_span!("top").entered();
+            // the ' ' requires quoting
+            let _middle = info_span!("mid dle").entered();
+            let _bottom = info_span!("bottom").entered();
+
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        let span = make_ansi_key_value("span", "=bottom");
+        let span_path = make_ansi_key_value("span_path", "=\"top>mid dle>bottom\"");
+        let ts = make_ansi_key_value("ts", "=20");
+
+        println!("{:?}", content);
+        assert!(content.contains(&span));
+        assert!(content.contains(&span_path));
+        assert!(content.contains("info"));
+        assert!(content.contains(&ts));
+    }
+    #[test]
+    #[cfg(feature = "ansi_logs")]
+    fn test_span_and_span_path_without_quoting() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = subscriber().with_writer(mock_writer.clone()).finish();
+
+        subscriber::with_default(subscriber, || {
+            let _top = info_span!("top").entered();
+            let _middle = info_span!("middle").entered();
+            let _bottom = info_span!("bottom").entered();
+
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        let span = make_ansi_key_value("span", "=bottom");
+        let span_path = make_ansi_key_value("span_path", "=top>middle>bottom");
+        let ts = make_ansi_key_value("ts", "=20");
+
+        println!("{}", content);
+        assert!(content.contains(&span));
+        assert!(content.contains(&span_path));
+        assert!(content.contains("info"));
+        assert!(content.contains(&ts));
+    }
+
+    #[test]
+    #[cfg(feature = "ansi_logs")]
+    fn test_disable_span_and_span_path() {
+        use nu_ansi_term::Color;
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_span_name(false)
+            .with_span_path(false)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _top = info_span!("top").entered();
+            let _middle = info_span!("middle").entered();
+            let _bottom = info_span!("bottom").entered();
+
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+        let message = make_ansi_key_value("message", "=message");
+        let target = make_ansi_key_value("target", "=tracing_logfmt::formatter::tests");
+        let ts = make_ansi_key_value("ts", "=");
+
+        println!("{}", content);
+        assert!(!content.contains("span="));
+        assert!(!content.contains("span_path="));
+        assert!(content.contains(&Color::Green.bold().paint("info").to_string()));
+        assert!(content.contains(&ts));
+        assert!(content.contains(&target));
+        assert!(content.contains(&message));
+    }
+
+    #[test]
+    #[cfg(all(not(feature = "ansi_logs"), windows))]
+    fn test_enable_location() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_location(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _top = info_span!("top").entered();
+            let _middle = info_span!("middle").entered();
+            let _bottom = info_span!("bottom").entered();
+
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+        let split = content.split(r"location=src\formatter.rs:").last().unwrap();
+        let line = &split[..3];
+        assert!(line.parse::<u32>().is_ok());
+
+        println!("{}", content);
+        assert!(content.contains(r"location=src\formatter.rs:"));
+        assert!(content.contains("info"));
+    }
+
+    #[test]
+    #[cfg(all(not(feature = "ansi_logs"), not(windows)))]
+    fn test_enable_location() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_location(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _top = info_span!("top").entered();
+            let _middle = info_span!("middle").entered();
+            let _bottom = info_span!("bottom").entered();
+
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+        let split = content.split("location=src/formatter.rs:").last().unwrap();
+        let line = &split[..3];
+        assert!(line.parse::<u32>().is_ok());
+
+        println!("{}", content);
+        assert!(content.contains("location=src/formatter.rs:"));
+        assert!(content.contains("info"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_enable_module_path() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_module_path(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _top = info_span!("top").entered();
+            let _middle = info_span!("middle").entered();
+            let _bottom = info_span!("bottom").entered();
+
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert!(content.contains("module_path=tracing_logfmt::formatter::tests"));
+        assert!(content.contains("info"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_enable_level_and_severity() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_level(false)
+            .with_level_and_severity(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("message");
+            tracing::warn!("message");
+            tracing::error!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert!(content.contains("level=info severity=6 "));
+        assert!(content.contains("level=warn severity=4 "));
+        assert!(content.contains("level=error severity=3 "));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_duration_suffix_parsing() {
+        use std::time::Duration;
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_duration_suffix_parsing(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!(
+                elapsed = ?Duration::from_millis(1500),
+                wait = ?Duration::from_micros(3),
+                name = "5s",
+                "message"
+            );
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert!(content.contains(" elapsed_ms=1500 "));
+        assert!(content.contains(" wait_ms=0.003 "));
+        // only debug values are parsed, strings are left as is
+        assert!(content.contains(" name=5s"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_duration_suffix_parsing_keeps_message() {
+        use std::time::Duration;
+
+        let builder = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_duration_suffix_parsing(true);
+
+        let output = builder.clone().capture(|| {
+            tracing::info!("{:?}", Duration::from_millis(1500));
+        });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info message=1.5s\n"
+        );
+
+        let output = builder.with_bare_message(true).capture(|| {
+            tracing::info!("200ms");
+        });
+        assert_eq!(String::from_utf8(output).unwrap(), "200ms level=info\n");
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_duration_suffix_parsing_denylist_and_rename() {
+        use std::time::Duration;
+
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_duration_suffix_parsing(true)
+            .with_field_denylist(["secret"])
+            .with_field_rename("elapsed", "took")
+            .capture(|| {
+                tracing::info!(
+                    elapsed = ?Duration::from_millis(1500),
+                    secret = ?Duration::from_millis(20),
+                    "message"
+                );
+            });
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info message=message took_ms=1500\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_sequence_numbers() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_sequence_numbers(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("first");
+            tracing::info!("second");
+            tracing::info!("third");
+        });
+
+        let content = mock_writer.get_content();
+        let sequence = content
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect::<Vec<_>>();
+
+        println!("{}", content);
+        assert_eq!(sequence, ["seq=0", "seq=1", "seq=2"]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "event_id", not(feature = "ansi_logs")))]
+    fn test_event_id() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_event_id(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("first");
+            tracing::info!("second");
+        });
+
+        let content = mock_writer.get_content();
+        let ids = content
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .map(|id| id.strip_prefix("event_id=").unwrap())
+            .map(|id| uuid::Uuid::parse_str(id).unwrap())
+            .collect::<Vec<_>>();
+
+        println!("{}", content);
+        assert_eq!(ids.len(), 2);
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_span_ref_root() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_span_ref(SpanRef::Root)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _top = info_span!("top").entered();
+            let _middle = info_span!("middle").entered();
+            let _bottom = info_span!("bottom").entered();
+
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert!(content.contains("span=top "));
+        assert!(content.contains("span_path=top>middle>bottom"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_field_order() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_field_order([BuiltinField::Level, BuiltinField::Span])
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _top = info_span!("top").entered();
+            let _bottom = info_span!("bottom").entered();
+
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+        let keys = content
+            .split_whitespace()
+            .map(|pair| pair.split('=').next().unwrap())
+            .collect::<Vec<_>>();
+
+        println!("{}", content);
+        assert_eq!(
+            keys,
+            ["level", "span", "ts", "target", "span_path", "message"]
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_quoted_keys() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_quoted_keys(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!("span", "my key" = 1).entered();
+
+            tracing::info!("other key" = "value", "message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert!(content.contains(r#" "other key"=value "#));
+        assert!(content.contains(r#" "my key"=1"#));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_dotted_field_names() {
+        use tracing::subscriber;
+
+        for quoted_keys in [false, true] {
+            let mock_writer = MockMakeWriter::new();
+            let subscriber = builder::builder()
+                .with_timestamp(false)
+                .with_quoted_keys(quoted_keys)
+                .subscriber_builder()
+                .with_writer(mock_writer.clone())
+                .finish();
+
+            subscriber::with_default(subscriber, || {
+                let _span = info_span!("db", db.host = "localhost", db.port = 5432).entered();
+
+                tracing::info!(query.rows = 3, "message");
+            });
+
+            let content = mock_writer.get_content();
+
+            println!("{}", content);
+            assert!(content.contains(" query.rows=3 "));
+            assert!(content.ends_with(" db.host=localhost db.port=5432\n"));
+        }
+    }
+
+    #[test]
+    fn test_writer_detects_non_terminal() {
+        use std::fs::File;
+
+        use tracing::subscriber;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let path = std::env::temp_dir().join(format!(
+            "tracing-logfmt-test-writer-{}.log",
+            std::process::id()
+        ));
+        let file = File::create(&path).unwrap();
+
+        let subscriber = tracing_subscriber::registry().with(
+            builder::builder()
+                .with_timestamp(false)
+                .writer(move || file.try_clone().unwrap()),
+        );
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("message");
+        });
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // a file is not a terminal, so there are no ansi color sequences
+        assert_eq!(
+            content,
+            "level=info target=tracing_logfmt::formatter::tests message=message\n"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "tracing-appender", not(feature = "ansi_logs")))]
+    fn test_rotating_file() {
+        use tracing::subscriber;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let directory = std::env::temp_dir().join(format!(
+            "tracing-logfmt-test-rotating-file-{}",
+            std::process::id()
+        ));
+        let (layer, guard) = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .rotating_file(&directory, "app.log");
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("message");
+        });
+        // flushes the lines that are still buffered
+        drop(guard);
+
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        let content = std::fs::read_to_string(&files[0]).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert!(files[0]
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("app.log."));
+        assert_eq!(content, "level=info message=message\n");
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_strip_newlines() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_strip_newlines(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!("span", text = "span\nfield").entered();
+
+            tracing::info!("multi\nline\r\nmessage");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert!(content.contains(" message=multi␤line␤message "));
+        assert!(content.ends_with(" text=span␤field\n"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_fixed_clock() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_clock(Arc::new(
+                || time::macros::datetime!(2024-01-02 03:04:05.678 UTC),
+            ))
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!("span").entered();
+
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        assert_eq!(
+            content,
+            "ts=2024-01-02T03:04:05.678Z level=info target=tracing_logfmt::formatter::tests span=span span_path=span message=message\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_kind() {
+        use tracing::subscriber;
+        use tracing_subscriber::fmt::format::FmtSpan;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = tracing_subscriber::fmt::Subscriber::builder()
+            .with_span_events(FmtSpan::NEW)
+            .event_format(EventsFormatter {
+                with_timestamp: false,
+                with_target: false,
+                with_kind: true,
+                ..Default::default()
+            })
+            .fmt_fields(FieldsFormatter::default())
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!("span").entered();
+
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        assert_eq!(
+            content,
+            "level=info kind=span span_lifecycle=new span=span span_path=span message=new\n\
+             level=info kind=event span=span span_path=span message=message\n"
+        );
+    }
+
+    #[test]
+    #[cfg(all(tracing_unstable, feature = "valuable", not(feature = "ansi_logs")))]
+    fn test_valuable() {
+        use tracing::subscriber;
+        use valuable::Valuable;
+
+        #[derive(Valuable)]
+        struct Address {
+            city: String,
+        }
+
+        #[derive(Valuable)]
+        struct User {
+            id: u64,
+            name: &'static str,
+            admin: bool,
+            address: Address,
+            tags: Vec<&'static str>,
+        }
+
+        let user = User {
+            id: 5,
+            name: "bob",
+            admin: false,
+            address: Address {
+                city: "New York".to_owned(),
+            },
+            tags: vec!["a", "b"],
+        };
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!(user = user.as_value(), "message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert!(content.ends_with(
+            r#" user.id=5 user.name=bob user.admin=false user.address.city="New York" user.tags="[\"a\", \"b\"]"
+"#
+        ));
+    }
+
+    #[test]
+    #[cfg(all(tracing_unstable, feature = "valuable", not(feature = "ansi_logs")))]
+    fn test_valuable_without_dotted_fields() {
+        use valuable::Valuable;
+
+        #[derive(Valuable)]
+        struct Db {
+            host: &'static str,
+            port: u16,
+        }
+
+        let db = Db {
+            host: "localhost",
+            port: 5432,
+        };
+        let capture = |builder: builder::Builder| {
+            let output = builder
+                .with_timestamp(false)
+                .with_target(false)
+                .with_span_name(false)
+                .with_span_path(false)
+                .capture(|| {
+                    let _span = info_span!("span", db = db.as_value(), db.name = "app").entered();
+                    tracing::info!("message");
+                });
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            capture(builder::builder()),
+            "level=info message=message db.host=localhost db.port=5432 db.name=app\n"
+        );
+        // field names with dots are kept either way
+        assert_eq!(
+            capture(builder::builder().with_dotted_fields(false)),
+            "level=info message=message db=\"Db { host: \\\"localhost\\\", port: 5432 }\" \
+             db.name=app\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_duplicate_field_policy() {
+        use tracing::subscriber;
+
+        let tests = vec![
+            (DuplicatePolicy::KeepAll, " a=1 b=x a=2 c=true a=\"3 4\"\n"),
+            (DuplicatePolicy::KeepFirst, " a=1 b=x c=true\n"),
+            (DuplicatePolicy::KeepLast, " b=x c=true a=\"3 4\"\n"),
+        ];
+
+        for (policy, expected_output) in tests {
+            let mock_writer = MockMakeWriter::new();
+            let subscriber = builder::builder()
+                .with_timestamp(false)
+                .with_duplicate_field_policy(policy)
+                .subscriber_builder()
+                .with_writer(mock_writer.clone())
+                .finish();
+
+            subscriber::with_default(subscriber, || {
+                tracing::info!(a = 1, b = "x", a = 2, c = true, a = "3 4");
+            });
+
+            let content = mock_writer.get_content();
+
+            println!("{}", content);
+            assert!(content.ends_with(expected_output), "{:?}", policy);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_verbose_level() {
+        use tracing::{subscriber, Level};
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_location(true)
+            .with_verbose_level(Level::DEBUG)
+            .subscriber_builder()
+            .with_max_level(Level::TRACE)
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!("span").entered();
+
+            tracing::info!("info");
+            tracing::debug!("debug");
+            tracing::trace!("trace");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(lines[0], "level=info span=span message=info");
+        for line in &lines[1..] {
+            assert!(line.contains(" target=tracing_logfmt::formatter::tests "));
+            assert!(line.contains(" location=src/formatter.rs:"));
+            assert!(line.contains(" span_path=span "));
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_default_message() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_default_message("")
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!(a = 1);
+            tracing::info!(a = 1, "message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert_eq!(
+            content,
+            "level=info a=1 message=\nlevel=info message=message a=1\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_quoted_target() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_quoted_target(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("message");
+            tracing::info!(target: "my_crate::foo<Bar>", "message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert_eq!(
+            content,
+            r#"level=info target="tracing_logfmt::formatter::tests" message=message
+level=info target="my_crate::foo<Bar>" message=message
+"#
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_field_options() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path(false)
+            .with_field_rename("message", "msg")
+            .with_field_rename("user", "user_id")
+            .with_field_denylist(["password"])
+            .with_max_value_length(5)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!(
+                "span",
+                user = 1234567,
+                password = "hunter2",
+                path = "/ab/cde"
+            )
+            .entered();
+
+            tracing::info!(password = "hunter2", note = "truncated", "message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert_eq!(
+            content,
+            "level=info msg=messa note=trunc user_id=1234567 path=/ab/c\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_invalid_key() {
+        use tracing::subscriber;
+
+        let tests = vec![
+            (false, "level=info a=1 b=2\n"),
+            (true, "level=info a=1 b=2 logfmt_error=invalid_key\n"),
+        ];
+
+        for (with_error_field, expected_output) in tests {
+            let mock_writer = MockMakeWriter::new();
+            let subscriber = builder::builder()
+                .with_timestamp(false)
+                .with_target(false)
+                .with_error_field(with_error_field)
+                .subscriber_builder()
+                .with_writer(mock_writer.clone())
+                .finish();
+
+            subscriber::with_default(subscriber, || {
+                tracing::info!(a = 1, "=" = 5, b = 2);
+            });
+
+            let content = mock_writer.get_content();
+
+            println!("{}", content);
+            assert_eq!(content, expected_output);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_span_fields_once() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_fields_once(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _outer = info_span!("outer", a = 1).entered();
+            tracing::info!("first");
+            {
+                let _inner = info_span!("inner", b = 2).entered();
+                tracing::info!("second");
+                tracing::info!("third");
+            }
+            tracing::info!("fourth");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert_eq!(
+            content,
+            "level=info span=outer span_path=outer message=first a=1
+level=info span=inner span_path=outer>inner message=second b=2
+level=info span=inner span_path=outer>inner message=third
+level=info span=outer span_path=outer message=fourth
+"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_span_fields_once_per_layer() {
+        use tracing::subscriber;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let builder = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path(false)
+            .with_span_fields_once(true);
+        let first_writer = MockMakeWriter::new();
+        let second_writer = MockMakeWriter::new();
+        let subscriber = tracing_subscriber::registry()
+            .with(builder.clone().layer().with_writer(first_writer.clone()))
+            .with(builder.layer().with_writer(second_writer.clone()));
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!("span", a = 1).entered();
+            tracing::info!("first");
+            tracing::info!("second");
+        });
+
+        let expected = "level=info message=first a=1\nlevel=info message=second\n";
+        assert_eq!(first_writer.get_content(), expected);
+        assert_eq!(second_writer.get_content(), expected);
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_span_fields_once_with_max_fields() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path(false)
+            .with_span_fields_once(true)
+            .with_max_fields(3)
+            .capture(|| {
+                let _span = info_span!("span", a = 1, b = 2).entered();
+                tracing::info!(c = 3, "first");
+                tracing::info!("second");
+                tracing::info!("third");
+            });
+
+        // the fields cut off on the first line are written on the next one
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info message=first c=3 a=1 fields_truncated=true\n\
+             level=info message=second b=2\n\
+             level=info message=third\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_quote_numbers() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path(false)
+            .with_quote_numbers(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!("span", id = 7_u64).entered();
+
+            tracing::info!(count = 5, ratio = 0.5, ok = true, name = "bob");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert_eq!(
+            content,
+            "level=info count=\"5\" ratio=\"0.5\" ok=\"true\" name=bob id=\"7\"\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_fallback_key() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_fallback_key("field")
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!(a = 1, "=" = 5, b = 2, " " = "x");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert_eq!(content, "level=info a=1 field_1=5 b=2 field_2=x\n");
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_from_config() {
+        use tracing::subscriber;
+
+        let config = crate::LogfmtConfig {
+            timestamp: false,
+            target: false,
+            field_renames: [("message".to_owned(), "msg".to_owned())].into(),
+            ..Default::default()
+        };
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::Builder::from_config(config)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert_eq!(content, "level=info msg=message\n");
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_metadata_suffix() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_metadata_position(MetadataPosition::Suffix)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!("span", a = 1).entered();
+
+            tracing::info!(b = 2, "message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert_eq!(
+            content,
+            "message=message b=2 a=1 level=info target=tracing_logfmt::formatter::tests span=span span_path=span\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_caller() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_caller(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        let mut line = 0;
+        subscriber::with_default(subscriber, || {
+            line = line!() + 1;
+            tracing::info!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert_eq!(
+            content,
+            format!(
+                "level=info caller=src/formatter.rs:{} message=message\n",
+                line
+            )
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_crate_name() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_crate_name(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("message");
+            tracing::info!(target: "my_crate", "message");
+            tracing::info!(target: "", "message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert_eq!(
+            content,
+            "level=info crate=tracing_logfmt message=message
+level=info crate=my_crate message=message
+level=info message=message
+"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_span_without_fields() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path(false)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _empty = info_span!("empty").entered();
+            tracing::info!("first");
+
+            let _span = info_span!("span", a = 1).entered();
+            let _empty = info_span!("empty").entered();
+            tracing::info!("second");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert_eq!(
+            content,
+            "level=info message=first\nlevel=info message=second a=1\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_skip_blank_message() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_skip_blank_message(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!(a = 1, "");
+            tracing::info!(a = 1, "   ");
+            tracing::info!(a = 1, " message ");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert_eq!(
+            content,
+            "level=info a=1\nlevel=info a=1\nlevel=info message=\" message \" a=1\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_extra_level_field() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_extra_level_field("level_num", |level| syslog_severity(&level).to_string())
+            .with_extra_level_field("LEVEL", |level| level.as_str().to_owned())
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::warn!("message");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        assert_eq!(
+            content,
+            "level=warn level_num=4 LEVEL=WARN message=message\n"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "otel", not(feature = "ansi_logs")))]
+    fn test_otel_ids() {
+        use opentelemetry::trace::TracerProvider;
+        use tracing::subscriber;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let mock_writer = MockMakeWriter::new();
+        let tracer = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+            .build()
+            .tracer("test");
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer))
+            .with(
+                builder::builder()
+                    .with_timestamp(false)
+                    .with_target(false)
+                    .with_span_name(false)
+                    .with_span_path(false)
+                    .with_otel_ids(true)
+                    .layer()
+                    .with_writer(mock_writer.clone()),
+            );
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("outside");
+
+            let _span = info_span!("span").entered();
+            tracing::info!("inside");
+        });
+
+        let content = mock_writer.get_content();
+
+        println!("{}", content);
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(lines[0], "level=info message=outside");
+
+        let ids: Vec<_> = lines[1]
+            .strip_prefix("level=info ")
+            .unwrap()
+            .strip_suffix(" message=inside")
+            .unwrap()
+            .split(' ')
+            .collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids[0].starts_with("trace_id="));
+        assert_eq!(ids[0].len(), "trace_id=".len() + 32);
+        assert!(ids[1].starts_with("span_id="));
+        assert_eq!(ids[1].len(), "span_id=".len() + 16);
+    }
+
+    #[test]
+    fn test_try_build() {
+        use crate::BuilderError;
+
+        let tests = vec![
+            (builder::builder(), None),
+            (builder::builder().with_field_rename("message", "msg"), None),
+            (
+                builder::builder().with_field_rename("message", " "),
+                Some(("with_field_rename", " ")),
+            ),
+            (
+                builder::builder().with_extra_level_field("=", |level| level.to_string()),
+                Some(("with_extra_level_field", "=")),
+            ),
+            (
+                builder::builder().with_fallback_key(""),
+                Some(("with_fallback_key", "")),
+            ),
+            (
+                builder::builder().with_level_key("="),
+                Some(("with_level_key", "=")),
+            ),
+            (
+                builder::builder().with_static_field(" ", "checkout"),
+                Some(("with_static_field", " ")),
+            ),
+            (
+                builder::builder()
+                    .with_additional_timestamp("=", crate::TimestampFormat::UnixMillis),
+                Some(("with_additional_timestamp", "=")),
+            ),
+            (
+                builder::builder()
+                    .with_quoted_keys(true)
+                    .with_field_rename("message", "my message"),
+                None,
+            ),
+        ];
+
+        for (builder, expected_error) in tests {
+            let error = builder.try_build().err();
+            let expected_error = expected_error.map(|(setting, key)| BuilderError::InvalidKey {
+                setting,
+                key: key.to_owned(),
+            });
+            assert_eq!(error, expected_error);
+        }
+    }
+
+    #[test]
+    fn test_try_build_quote_char() {
+        use crate::BuilderError;
+
+        for quote in ['\'', '|', '`'] {
+            let builder = builder::builder().with_quote_char(quote);
+            assert!(builder.try_build().is_ok(), "{:?}", quote);
+        }
+        for quote in ['a', '1', ' ', '=', '\\'] {
+            let builder = builder::builder().with_quote_char(quote);
+            assert_eq!(
+                builder.try_build().err(),
+                Some(BuilderError::InvalidQuoteChar(quote))
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid key \"=\" set with `with_fallback_key`")]
+    fn test_build_panics_on_invalid_key() {
+        let _ = builder::builder()
+            .with_fallback_key("=")
+            .layer::<tracing_subscriber::Registry>();
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_line_ending() {
+        use tracing::subscriber;
+
+        let tests = vec![
+            (
+                LineEnding::Lf,
+                "level=info message=a\nlevel=info message=b\n",
+            ),
+            (
+                LineEnding::CrLf,
+                "level=info message=a\r\nlevel=info message=b\r\n",
+            ),
+        ];
+
+        for (line_ending, expected_output) in tests {
+            let mock_writer = MockMakeWriter::new();
+            let subscriber = builder::builder()
+                .with_timestamp(false)
+                .with_target(false)
+                .with_line_ending(line_ending)
+                .subscriber_builder()
+                .with_writer(mock_writer.clone())
+                .finish();
+
+            subscriber::with_default(subscriber, || {
+                tracing::info!("a");
+                tracing::info!("b");
+            });
+
+            assert_eq!(mock_writer.get_content(), expected_output);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_trailing_newline() {
+        use tracing::subscriber;
+
+        let tests = vec![
+            (true, "level=info message=a\nlevel=info message=b\n"),
+            (false, "level=info message=alevel=info message=b"),
+        ];
+
+        for (enable, expected_output) in tests {
+            let mock_writer = MockMakeWriter::new();
+            let subscriber = builder::builder()
+                .with_timestamp(false)
+                .with_target(false)
+                .with_trailing_newline(enable)
+                .subscriber_builder()
+                .with_writer(mock_writer.clone())
+                .finish();
+
+            subscriber::with_default(subscriber, || {
+                tracing::info!("a");
+                tracing::info!("b");
+            });
+
+            assert_eq!(mock_writer.get_content(), expected_output);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_field_grouping() {
+        use tracing::subscriber;
+
+        let err = io::Error::other("boom");
+        let cause: &(dyn std::error::Error + 'static) = &err;
+
+        let tests = vec![
+            (
+                false,
+                "level=info a=1 error.kind=timeout message=hi cause=boom b=2\n",
+            ),
+            (
+                true,
+                "level=info message=hi a=1 b=2 error.kind=timeout cause=boom\n",
+            ),
+        ];
+
+        for (enable, expected_output) in tests {
+            let mock_writer = MockMakeWriter::new();
+            let subscriber = builder::builder()
+                .with_timestamp(false)
+                .with_target(false)
+                .with_field_grouping(enable)
+                .subscriber_builder()
+                .with_writer(mock_writer.clone())
+                .finish();
+
+            subscriber::with_default(subscriber, || {
+                tracing::info!(a = 1, error.kind = "timeout", message = "hi", cause, b = 2);
+            });
+
+            assert_eq!(mock_writer.get_content(), expected_output);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_no_span() {
+        use tracing::subscriber;
+
+        let builder = builder::builder().with_timestamp(false).with_target(false);
+        let tests = vec![
+            (builder.clone(), "level=info message=hi\n"),
+            (
+                builder.clone().with_field_order([
+                    BuiltinField::Span,
+                    BuiltinField::SpanPath,
+                    BuiltinField::Level,
+                ]),
+                "level=info message=hi\n",
+            ),
+            (
+                builder
+                    .clone()
+                    .with_metadata_position(MetadataPosition::Suffix)
+                    .with_span_fields_once(true),
+                "message=hi level=info\n",
+            ),
+            (
+                builder.with_span_ref(SpanRef::Root),
+                "level=info message=hi\n",
+            ),
+        ];
+
+        for (builder, expected_output) in tests {
+            let make_subscriber = |mock_writer: MockMakeWriter| {
+                builder
+                    .clone()
+                    .subscriber_builder()
+                    .with_writer(mock_writer)
+                    .finish()
+            };
+
+            // no span at all
+            let mock_writer = MockMakeWriter::new();
+            subscriber::with_default(make_subscriber(mock_writer.clone()), || {
+                tracing::info!("hi");
+            });
+            assert_eq!(mock_writer.get_content(), expected_output);
+
+            // an explicit root event inside a span
+            let mock_writer = MockMakeWriter::new();
+            subscriber::with_default(make_subscriber(mock_writer.clone()), || {
+                let _enter = info_span!("outer", a = 1).entered();
+                tracing::info!(parent: None, "hi");
+            });
+            assert_eq!(mock_writer.get_content(), expected_output);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_quoted_span_path() {
+        use tracing::subscriber;
+
+        let tests = vec![
+            (false, "outer", "span=inner span_path=outer>inner"),
+            (true, "outer", "span=inner span_path=\"outer>inner\""),
+            (true, "out er", "span=inner span_path=\"out er>inner\""),
+        ];
+
+        for (enable, outer_name, expected_output) in tests {
+            let mock_writer = MockMakeWriter::new();
+            let subscriber = builder::builder()
+                .with_timestamp(false)
+                .with_target(false)
+                .with_level(false)
+                .with_quoted_span_path(enable)
+                .subscriber_builder()
+                .with_writer(mock_writer.clone())
+                .finish();
+
+            subscriber::with_default(subscriber, || {
+                let outer = if outer_name == "outer" {
+                    info_span!("outer")
+                } else {
+                    info_span!("out er")
+                };
+                let _outer = outer.entered();
+                let _inner = info_span!("inner").entered();
+                tracing::info!("hi");
+            });
+
+            assert_eq!(
+                mock_writer.get_content(),
+                format!("{} message=hi\n", expected_output)
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_uptime() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_uptime(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("a");
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            tracing::info!("b");
+        });
+
+        let content = mock_writer.get_content();
+        let uptimes: Vec<u128> = content
+            .lines()
+            .map(|line| {
+                let uptime = line
+                    .strip_prefix("uptime_ms=")
+                    .and_then(|line| line.split(' ').next())
+                    .unwrap();
+                uptime.parse().unwrap()
+            })
+            .collect();
+
+        assert_eq!(uptimes.len(), 2, "{}", content);
+        assert!(uptimes[1] >= uptimes[0] + 5, "{}", content);
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_value_transform() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_value_transform(|key, value| match key {
+                "mode" => Some(Cow::Owned(value.to_lowercase())),
+                "password" => None,
+                "count" => Some(Cow::Owned(format!("{}0", value))),
+                _ => Some(Cow::Borrowed(value)),
+            })
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!("span", mode = "FAST").entered();
+            tracing::info!(
+                mode = "SLOW",
+                password = "hunter2",
+                count = 4,
+                ok = true,
+                "hi"
+            );
+        });
+
+        assert_eq!(
+            mock_writer.get_content(),
+            "level=info span=span span_path=span message=hi mode=slow count=40 ok=true mode=fast\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_short_level() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_short_level(true)
+            .with_level_and_severity(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .with_max_level(tracing::Level::TRACE)
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::error!("a");
+            tracing::warn!("a");
+            tracing::info!("a");
+            tracing::debug!("a");
+            tracing::trace!("a");
+        });
+
+        assert_eq!(
+            mock_writer.get_content(),
+            "level=E severity=3 message=a\n\
+             level=W severity=4 message=a\n\
+             level=I severity=6 message=a\n\
+             level=D severity=7 message=a\n\
+             level=T severity=7 message=a\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_span_field_count() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path(false)
+            .with_span_field_count(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("a");
+            let _outer = info_span!("outer", a = 1, b = "x y").entered();
+            let _inner = info_span!("inner", c = true).entered();
+            tracing::info!(d = 4, "b");
+        });
+
+        assert_eq!(
+            mock_writer.get_content(),
+            "level=info message=a span_fields=0\n\
+             level=info message=b d=4 a=1 b=\"x y\" c=true span_fields=3\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_span_name_id_combined() {
+        use tracing::subscriber;
+
+        let tests = vec![('#', "span=inner#", false), (' ', "span=\"inner ", true)];
+
+        for (joiner, expected_prefix, quoted) in tests {
+            let mock_writer = MockMakeWriter::new();
+            let subscriber = builder::builder()
+                .with_timestamp(false)
+                .with_target(false)
+                .with_level(false)
+                .with_span_path(false)
+                .with_span_name_id_combined(true)
+                .with_span_name_id_joiner(joiner)
+                .subscriber_builder()
+                .with_writer(mock_writer.clone())
+                .finish();
+
+            let mut span_id = 0;
+            subscriber::with_default(subscriber, || {
+                let _outer = info_span!("outer").entered();
+                let inner = info_span!("inner").entered();
+                span_id = inner.id().unwrap().into_u64();
+                tracing::info!("hi");
+            });
+
+            let mut expected_output = format!("{}{}", expected_prefix, span_id);
+            if quoted {
+                expected_output.push('"');
+            }
+            expected_output.push_str(" message=hi\n");
+            assert_eq!(mock_writer.get_content(), expected_output);
+        }
+    }
+
+    #[test]
+    fn test_encoded_bytes() {
+        let tests = vec![
+            (&b""[..], "", ""),
+            (b"f", "66", "Zg"),
+            (b"fo", "666f", "Zm8"),
+            (b"foo", "666f6f", "Zm9v"),
+            (b"foob", "666f6f62", "Zm9vYg"),
+            (b"\x00\xff\xfe", "00fffe", "AP/+"),
+        ];
+
+        for (bytes, hex, base64) in tests {
+            let encode = |encoding| format!("{:?}", EncodedBytes { bytes, encoding });
+            assert_eq!(encode(BytesEncoding::Hex), hex);
+            assert_eq!(encode(BytesEncoding::Base64), base64);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_bytes_encoding() {
+        use tracing::subscriber;
+
+        let tests = vec![
+            (BytesEncoding::Hex, "level=info message=a data=6869\n"),
+            (BytesEncoding::Base64, "level=info message=a data=aGk\n"),
+        ];
+
+        for (encoding, expected_output) in tests {
+            let mock_writer = MockMakeWriter::new();
+            let subscriber = builder::builder()
+                .with_timestamp(false)
+                .with_target(false)
+                .with_bytes_encoding(encoding)
+                .subscriber_builder()
+                .with_writer(mock_writer.clone())
+                .finish();
+
+            subscriber::with_default(subscriber, || {
+                tracing::info!(data = &b"hi"[..], "a");
+            });
+
+            assert_eq!(mock_writer.get_content(), expected_output);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_root_span_fields() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path(false)
+            .with_root_span_fields(true)
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("a");
+            let _outer = info_span!("outer", id = 1, user = "x y").entered();
+            let _inner = info_span!("inner", c = true).entered();
+            tracing::info!(root.user = "z", "b");
+        });
+
+        assert_eq!(
+            mock_writer.get_content(),
+            "level=info message=a\n\
+             level=info message=b root.user=z root.id=1 id=1 user=\"x y\" c=true\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_without_metadata() {
+        use tracing::subscriber;
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_location(true)
+            .with_module_path(true)
+            .with_kind(true)
+            .with_sequence_numbers(true)
+            .without_metadata()
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!("span", a = 1).entered();
+            tracing::info!(b = 2, "hi");
+        });
+
+        assert_eq!(mock_writer.get_content(), "message=hi b=2 a=1\n");
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_target_level_override() {
+        use tracing::{subscriber, Level};
+
+        let mock_writer = MockMakeWriter::new();
+        let subscriber = builder::builder()
+            .with_timestamp(false)
+            .with_level_and_severity(true)
+            .with_target_level_override([
+                ("noisy", Level::DEBUG),
+                ("noisy::important", Level::WARN),
+            ])
+            .subscriber_builder()
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!(target: "noisy", "a");
+            tracing::info!(target: "noisy::client", "a");
+            tracing::info!(target: "noisy::important::x", "a");
+            tracing::info!(target: "noisyish", "a");
+        });
+
+        assert_eq!(
+            mock_writer.get_content(),
+            "level=debug severity=6 target=noisy message=a\n\
+             level=debug severity=6 target=noisy::client message=a\n\
+             level=warn severity=6 target=noisy::important::x message=a\n\
+             level=info severity=6 target=noisyish message=a\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_missing_formatted_fields() {
+        use tracing_subscriber::layer::{Context, SubscriberExt};
+
+        /// Removes the formatted fields of every span right after they are
+        /// added by the fmt layer.
+        struct RemoveFormattedFields;
+
+        impl<S> Layer<S> for RemoveFormattedFields
+        where
+            S: Subscriber + for<'a> LookupSpan<'a>,
+        {
+            fn on_new_span(
+                &self,
+                _attrs: &tracing_core::span::Attributes<'_>,
+                id: &tracing_core::span::Id,
+                ctx: Context<'_, S>,
+            ) {
+                let span = ctx.span(id).unwrap();
+                span.extensions_mut()
+                    .remove::<FormattedFields<FieldsFormatter>>();
+            }
+        }
+
+        let mock_writer = MockMakeWriter::new();
+        let (events, fields) = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_root_span_fields(true)
+            .with_span_field_count(true)
+            .build();
+        let subscriber = tracing_subscriber::Registry::default()
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .event_format(events)
+                    .fmt_fields(fields)
+                    .with_writer(mock_writer.clone()),
+            )
+            .with(RemoveFormattedFields);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = info_span!("span", a = 1).entered();
+            tracing::info!("hi");
+        });
+
+        assert_eq!(
+            mock_writer.get_content(),
+            "level=info span=span span_path=span message=hi span_fields=0\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_quote_char() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_quote_char('\'')
+            .with_quoted_span_path(true)
+            .with_root_span_fields(true)
+            .capture(|| {
+                let _span = info_span!("span", a = "x y").entered();
+                tracing::info!(b = "it's", c = "\"q\"", "hi");
+            });
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info span=span span_path='span' message=hi b='it\\'s' c='\"q\"' \
+             root.a='x y' a='x y'\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_event_name() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_event_name(true)
+            .capture(|| {
+                tracing::info!(name: "cache_miss", key = "a", "lookup");
+            });
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info name=cache_miss message=lookup key=a\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_span_duration_format() {
+        use tracing::subscriber;
+        use tracing_subscriber::fmt::format::FmtSpan;
+
+        for (format, busy_key) in [
+            (DurationFormat::Nanos, "time.busy_ns="),
+            (DurationFormat::Millis, "time.busy_ms="),
+            (DurationFormat::Human, "time.busy="),
+        ] {
+            let mock_writer = MockMakeWriter::new();
+            let (events, fields) = builder::builder()
+                .with_timestamp(false)
+                .with_target(false)
+                .with_span_duration_format(format)
+                .build();
+            let subscriber = tracing_subscriber::fmt::Subscriber::builder()
+                .with_span_events(FmtSpan::CLOSE)
+                .event_format(events)
+                .fmt_fields(fields)
+                .with_writer(mock_writer.clone())
+                .finish();
+
+            subscriber::with_default(subscriber, || {
+                let _span = info_span!("span").entered();
+            });
+
+            let content = mock_writer.get_content();
+            let (_, rest) = content.split_once(busy_key).unwrap();
+            let value = rest.split(' ').next().unwrap();
+            match format {
+                DurationFormat::Human => {
+                    assert!(
+                        duration::parse_debug_duration(value).is_some(),
+                        "{}",
+                        content
+                    );
+                }
+                _ => assert!(value.parse::<f64>().is_ok(), "{}", content),
+            }
+            assert!(content.starts_with(
+                "level=info span_lifecycle=close span=span span_path=span message=close "
+            ));
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_span_duration_format_denylist_and_rename() {
+        use tracing::subscriber;
+        use tracing_subscriber::fmt::format::FmtSpan;
+
+        let mock_writer = MockMakeWriter::new();
+        let (events, fields) = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_duration_format(DurationFormat::Nanos)
+            .with_field_denylist(["time.idle"])
+            .with_field_rename("time.busy", "busy")
+            .build();
+        let subscriber = tracing_subscriber::fmt::Subscriber::builder()
+            .with_span_events(FmtSpan::CLOSE)
+            .event_format(events)
+            .fmt_fields(fields)
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            let _span = info_span!("span").entered();
+        });
+
+        let content = mock_writer.get_content();
+        assert!(content.contains(" busy_ns="), "{}", content);
+        assert!(!content.contains("time."), "{}", content);
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_static_field() {
+        let builder = builder::builder()
+            .with_timestamp(false)
+            .with_static_field("service", "checkout")
+            .with_static_field("env", "prod")
+            .with_static_field("owner", "team a");
+
+        let output = builder.clone().capture(|| {
+            tracing::info!(id = 1, "message");
+        });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info target=tracing_logfmt::formatter::tests service=checkout env=prod owner=\"team a\" message=message id=1\n"
+        );
+
+        let output = builder
+            .with_field_order([BuiltinField::Static])
+            .capture(|| {
+                tracing::info!("message");
+            });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "service=checkout env=prod owner=\"team a\" level=info target=tracing_logfmt::formatter::tests message=message\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_static_fields_from_env() {
+        use tracing::subscriber;
+
+        let vars = [
+            ("PATH", "/usr/bin"),
+            ("LOGFMT_FIELD_service", "checkout"),
+            ("LOGFMT_FIELD_env", "prod"),
+            ("LOGFMT_FIELD_", "empty"),
+            ("LOGFMT_FIELD_\"", "quote"),
+        ]
+        .map(|(name, value)| (name.into(), value.into()));
+
+        let mock_writer = MockMakeWriter::new();
+        let (events, fields) = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_static_fields_from_env("LOGFMT_FIELD_")
+            .into_formatters_with_vars(|| vars);
+        let subscriber = tracing_subscriber::fmt::Subscriber::builder()
+            .event_format(events)
+            .fmt_fields(fields)
+            .with_writer(mock_writer.clone())
+            .finish();
+
+        subscriber::with_default(subscriber, || {
+            tracing::info!("message");
+        });
+
+        // keys that can't be written are skipped instead of failing the build
+        assert_eq!(
+            mock_writer.get_content(),
+            "level=info env=prod service=checkout message=message\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_promoted_span_field() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path(false)
+            .with_promoted_span_field("component", "component")
+            .with_promoted_span_field("request", "req")
+            .with_promoted_span_field("missing", "missing")
+            .capture(|| {
+                let _outer = info_span!("outer", component = "checkout", request = 1).entered();
+                let _inner = info_span!("inner", request = 2).entered();
+                tracing::info!("message");
+            });
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info component=checkout req=2 message=message component=checkout request=1 request=2\n"
+        );
+    }
+
+    #[test]
+    fn test_to_config() {
+        use crate::{LogfmtConfig, SpanEvent};
+
+        assert_eq!(builder::Builder::new().to_config(), LogfmtConfig::default());
+
+        let config = LogfmtConfig {
+            target: false,
+            verbose_level: Some(tracing::Level::DEBUG),
+            field_renames: [("message".to_owned(), "msg".to_owned())].into(),
+            static_fields: vec![("service".to_owned(), "checkout".to_owned())],
+            static_fields_env_prefix: Some("LOGFMT_FIELD_".to_owned()),
+            field_denylist: vec!["password".to_owned(), "token".to_owned()],
+            span_duration_format: Some(DurationFormat::Human),
+            span_events: vec![SpanEvent::New, SpanEvent::Close],
+            newline_placeholder: Some("\\n".to_owned()),
+            quote_char: '\'',
+            ..Default::default()
+        };
+        assert_eq!(
+            builder::Builder::from_config(config.clone()).to_config(),
+            config
+        );
 
-        println!("{:?}", content);
-        assert!(content.contains("span=bottom"));
-        assert!(content.contains("span_path=\"top>mid dle>bottom\""));
-        assert!(content.contains("info"));
-        assert!(content.contains("ts=20"));
+        let config = builder::Builder::new()
+            .with_field_order([BuiltinField::Level, BuiltinField::Ts])
+            .to_config();
+        assert_eq!(config.field_order.len(), BuiltinField::DEFAULT_ORDER.len());
+        assert_eq!(
+            config.field_order[..2],
+            [BuiltinField::Level, BuiltinField::Ts]
+        );
+
+        let config = builder::Builder::new().without_metadata().to_config();
+        assert!(!config.level);
+        assert!(config.timestamp);
+
+        let config = builder::Builder::new()
+            .with_span_events(tracing_subscriber::fmt::format::FmtSpan::ACTIVE)
+            .to_config();
+        assert_eq!(config.span_events, [SpanEvent::Enter, SpanEvent::Exit]);
+    }
+
+    #[test]
+    fn test_getters() {
+        use tracing_subscriber::fmt::format::FmtSpan;
+
+        let builder = builder::Builder::new();
+        assert!(builder.level_enabled());
+        assert!(builder.timestamp_enabled());
+        assert_eq!(builder.level_key(), "level");
+        assert_eq!(builder.verbosity_field(), None);
+        assert_eq!(builder.span_events(), FmtSpan::NONE);
+
+        let clock: Arc<dyn Clock> = Arc::new(|| time::macros::datetime!(2024-01-02 03:04:05 UTC));
+        let builder = builder
+            .with_level(false)
+            .with_level_key("severity")
+            .with_metadata_quoting(true)
+            .with_static_field("service", "checkout")
+            .with_span_events(FmtSpan::CLOSE)
+            .with_clock(clock.clone());
+        assert!(!builder.level_enabled());
+        assert_eq!(builder.level_key(), "severity");
+        assert!(builder.metadata_quoting_enabled());
+        assert_eq!(
+            builder.static_fields(),
+            [("service".to_owned(), "checkout".to_owned())]
+        );
+        assert_eq!(builder.span_events(), FmtSpan::CLOSE);
+        assert!(Arc::ptr_eq(builder.clock(), &clock));
     }
 
     #[test]
     #[cfg(not(feature = "ansi_logs"))]
-    fn test_span_and_span_path_without_quoting() {
-        use tracing::subscriber;
+    fn test_128_bit_integers() {
+        let builder = builder::builder().with_timestamp(false).with_target(false);
+        let log = || tracing::info!(big = i128::MAX, small = i128::MIN, unsigned = u128::MAX);
 
-        let mock_writer = MockMakeWriter::new();
-        let subscriber = subscriber().with_writer(mock_writer.clone()).finish();
+        let output = builder.clone().capture(log);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info big=170141183460469231731687303715884105727 \
+             small=-170141183460469231731687303715884105728 \
+             unsigned=340282366920938463463374607431768211455\n"
+        );
 
-        subscriber::with_default(subscriber, || {
-            let _top = info_span!("top").entered();
-            let _middle = info_span!("middle").entered();
-            let _bottom = info_span!("bottom").entered();
+        // recorded like the other numbers, and not as debug values
+        let output = builder.with_quote_numbers(true).capture(log);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info big=\"170141183460469231731687303715884105727\" \
+             small=\"-170141183460469231731687303715884105728\" \
+             unsigned=\"340282366920938463463374607431768211455\"\n"
+        );
+    }
 
-            tracing::info!("message");
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_max_fields() {
+        let builder = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path(false)
+            .with_max_fields(3);
+
+        let output = builder.clone().capture(|| {
+            tracing::info!(a = 1, b = 2, c = 3, d = 4, "message");
         });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info message=message a=1 b=2 fields_truncated=true\n"
+        );
 
-        let content = mock_writer.get_content();
+        let output = builder.clone().capture(|| {
+            let _span = info_span!("span", x = "a b", y = 2).entered();
+            tracing::info!(a = 1);
+        });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info a=1 x=\"a b\" y=2\n"
+        );
 
-        println!("{:?}", content);
-        assert!(content.contains("span=bottom"));
-        assert!(content.contains("span_path=top>middle>bottom"));
-        assert!(content.contains("info"));
-        assert!(content.contains("ts=20"));
+        let output = builder.with_span_field_count(true).capture(|| {
+            let _outer = info_span!("outer", x = 1).entered();
+            let _inner = info_span!("inner", y = 2, z = 3).entered();
+            tracing::info!(a = 1);
+        });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info a=1 x=1 y=2 span_fields=2 fields_truncated=true\n"
+        );
     }
 
     #[test]
     #[cfg(not(feature = "ansi_logs"))]
-    fn test_disable_span_and_span_path() {
-        use tracing::subscriber;
+    fn test_span_path_ids() {
+        let builder = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path_ids(true);
 
-        let mock_writer = MockMakeWriter::new();
-        let subscriber = builder::builder()
+        let id = |span: &tracing::span::EnteredSpan| span.id().unwrap().into_u64();
+
+        let mut ids = (0, 0);
+        let output = builder.clone().capture(|| {
+            let outer = info_span!("outer").entered();
+            let inner = info_span!("in ner").entered();
+            ids = (id(&outer), id(&inner));
+            tracing::info!("message");
+        });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("level=info span_path={}>{} message=message\n", ids.0, ids.1)
+        );
+
+        let output = builder.with_quoted_span_path(true).capture(|| {
+            let outer = info_span!("outer").entered();
+            ids.0 = id(&outer);
+            tracing::info!("message");
+        });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("level=info span_path=\"{}\" message=message\n", ids.0)
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_skip_none_debug() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
             .with_span_name(false)
             .with_span_path(false)
-            .subscriber_builder()
-            .with_writer(mock_writer.clone())
-            .finish();
+            .with_skip_none_debug(true)
+            .capture(|| {
+                let _span = info_span!("span", parent = ?None::<u32>).entered();
+                tracing::info!(a = ?None::<u32>, b = ?Some(1), c = "None", "message");
+            });
 
-        subscriber::with_default(subscriber, || {
-            let _top = info_span!("top").entered();
-            let _middle = info_span!("middle").entered();
-            let _bottom = info_span!("bottom").entered();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info message=message b=Some(1) c=None\n"
+        );
+    }
 
-            tracing::info!("message");
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_skip_none_debug_keeps_message() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_skip_none_debug(true)
+            .capture(|| {
+                tracing::info!("None");
+                tracing::info!("{:?}", None::<u8>);
+            });
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info message=None\nlevel=info message=None\n"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", not(feature = "ansi_logs")))]
+    fn test_json_output() {
+        use crate::OutputFormat;
+
+        let output = builder::builder()
+            .with_target(false)
+            .with_clock(Arc::new(
+                || time::macros::datetime!(2024-01-02 03:04:05 UTC),
+            ))
+            .with_output_format(OutputFormat::Json)
+            .with_root_span_fields(true)
+            .with_promoted_span_field("user", "user")
+            .capture(|| {
+                let _span = info_span!("my span", user = "bob", "my id" = 2).entered();
+                tracing::info!(
+                    count = 5,
+                    ratio = f64::NAN,
+                    ok = true,
+                    text = "say \"hi\"\n",
+                    debug = ?Some(1),
+                    "message"
+                );
+            });
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(
+            output,
+            "{\"ts\":\"2024-01-02T03:04:05Z\",\"level\":\"info\",\"span\":\"my span\",\
+             \"span_path\":\"my span\",\"user\":\"bob\",\"message\":\"message\",\"count\":5,\
+             \"ratio\":\"NaN\",\"ok\":true,\"text\":\"say \\\"hi\\\"\\n\",\"debug\":\"Some(1)\",\
+             \"root.user\":\"bob\",\"root.my id\":2,\"user\":\"bob\",\"my id\":2}\n"
+        );
+        assert!(serde_json::from_str::<serde_json::Value>(&output).is_ok());
+    }
+
+    #[test]
+    #[cfg(all(feature = "json", not(feature = "ansi_logs")))]
+    fn test_json_recorded_span_fields() {
+        use crate::OutputFormat;
+
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_path(false)
+            .with_output_format(OutputFormat::Json)
+            .capture(|| {
+                let span = info_span!("span", a = 1, b = tracing::field::Empty);
+                span.record("b", 2);
+                let _span = span.entered();
+                tracing::info!("message");
+            });
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(
+            output,
+            "{\"level\":\"info\",\"span\":\"span\",\"message\":\"message\",\"a\":1,\"b\":2}\n"
+        );
+        assert!(serde_json::from_str::<serde_json::Value>(&output).is_ok());
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_grafana_defaults() {
+        let builder = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_grafana_defaults();
+        assert_eq!(builder.to_config().level_key, "lvl");
+
+        let output = builder.capture(|| {
+            tracing::warn!(id = 1, "message");
         });
 
-        let content = mock_writer.get_content();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "lvl=warn msg=message id=1\n"
+        );
+    }
 
-        println!("{:?}", content);
-        assert!(!content.contains("span="));
-        assert!(!content.contains("span_path="));
-        assert!(content.contains("level=info"));
-        assert!(content.contains("ts=20"));
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_explicit_parent_span_fields() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .capture(|| {
+                let parent = info_span!("parent", request = 7);
+                let _current = info_span!("current", other = 1).entered();
+                tracing::info!(parent: &parent, "message");
+            });
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info span=parent span_path=parent message=message request=7\n"
+        );
     }
 
     #[test]
     #[cfg(feature = "ansi_logs")]
-    fn test_disable_ansi_color() {
-        use tracing::subscriber;
+    fn test_level_colors() {
+        let colors = LevelColors {
+            warn: AnsiColor::Cyan,
+            ..LevelColors::default()
+        };
 
-        let mock_writer = MockMakeWriter::new();
-        let subscriber = builder::builder()
-            // disable timestamp so it can be asserted
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_ansi_color(true)
+            .with_level_colors(colors)
+            .capture(|| {
+                tracing::warn!("message");
+            });
+        let output = String::from_utf8(output).unwrap();
+        let level = nu_ansi_term::Color::Cyan.bold().paint("warn").to_string();
+        assert!(output.contains(&level), "{:?}", output);
+
+        let output = builder::builder()
             .with_timestamp(false)
+            .with_target(false)
             .with_ansi_color(false)
-            .subscriber_builder()
-            .with_writer(mock_writer.clone())
-            .finish();
+            .with_level_colors(colors)
+            .capture(|| {
+                tracing::warn!("message");
+            });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=warn message=message\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_timestamp_from_field() {
+        let capture = |builder: builder::Builder| {
+            let output = builder
+                .with_target(false)
+                .with_clock(Arc::new(
+                    || time::macros::datetime!(2024-01-02 03:04:05 UTC),
+                ))
+                .with_timestamp_from_field(Some("timestamp".to_owned()))
+                .capture(|| {
+                    tracing::info!(timestamp = "2020-05-06T07:08:09.5Z", "replayed");
+                    tracing::info!(timestamp = %"2020-05-06T07:08:09+02:00", "display");
+                    tracing::info!(timestamp = "yesterday", "unparseable");
+                    tracing::info!("absent");
+                });
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            capture(builder::builder()),
+            "ts=2020-05-06T07:08:09.5Z level=info message=replayed\n\
+             ts=2020-05-06T07:08:09+02:00 level=info message=display\n\
+             ts=2024-01-02T03:04:05Z level=info message=unparseable timestamp=yesterday\n\
+             ts=2024-01-02T03:04:05Z level=info message=absent\n"
+        );
+
+        // without a `ts`, the field is written as is
+        assert_eq!(
+            capture(builder::builder().with_timestamp(false))
+                .lines()
+                .next()
+                .unwrap(),
+            "level=info message=replayed timestamp=2020-05-06T07:08:09.5Z"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_span_events() {
+        use tracing_subscriber::fmt::format::FmtSpan;
+
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_path(false)
+            .with_kind(true)
+            .with_span_events(FmtSpan::NEW | FmtSpan::EXIT)
+            .capture(|| {
+                let _span = info_span!("request", id = 7).entered();
+                tracing::info!("handled");
+                // only the events from `FmtSpan` are lifecycle events
+                tracing::info!("exit");
+            });
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info kind=span span_lifecycle=new span=request message=new id=7\n\
+             level=info kind=event span=request message=handled id=7\n\
+             level=info kind=event span=request message=exit id=7\n\
+             level=info kind=span span_lifecycle=exit span=request message=exit id=7\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_version() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_version("1.2.3")
+            .with_static_field("service", "checkout")
+            .capture(|| {
+                tracing::info!("message");
+            });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info version=1.2.3 service=checkout message=message\n"
+        );
+
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_version("1.2.3 (beta)")
+            .capture(|| {
+                tracing::info!("message");
+            });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info version=\"1.2.3 (beta)\" message=message\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_git_sha() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_git_sha("abc123")
+            .with_version("1.2.3")
+            .with_static_field("service", "checkout")
+            .capture(|| {
+                tracing::info!("message");
+            });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info version=1.2.3 git_sha=abc123 service=checkout message=message\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_type_hints() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_type_hints(true)
+            .capture(|| {
+                let _span = info_span!("span", id = 3u64).entered();
+                tracing::info!(
+                    count = 5,
+                    name = "5",
+                    ratio = 0.5,
+                    ok = true,
+                    data = &b"\x01"[..],
+                    point = ?(1, 2),
+                    "message"
+                );
+            });
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info span=span span_path=span message=message count:int=5 name:str=5 \
+             ratio:float=0.5 ok:bool=true data:bytes=01 point=\"(1, 2)\" id:int=3\n"
+        );
+    }
+
+    #[test]
+    fn test_span_path_fast_path_matches_slow_path() {
+        // every path of up to two names of up to two chars from `chars`
+        let chars = ["a", "Z", " ", "=", "\"", "'", "\\", ">", "\n", "\x7f", "é"];
+        let mut names = vec![String::new()];
+        for a in chars {
+            names.push(a.to_owned());
+            for b in chars {
+                names.push(format!("{}{}", a, b));
+            }
+        }
+
+        let escape_modes = [crate::EscapeMode::Debug, crate::EscapeMode::Minimal];
+        for (escape_mode, always_quote) in escape_modes
+            .into_iter()
+            .flat_map(|mode| [(mode, false), (mode, true)])
+        {
+            let options = SerializerOptions {
+                escape_mode,
+                ..Default::default()
+            };
+            let write = |path: &[&str], escaped: bool| {
+                let mut output = String::new();
+                let mut serializer = Serializer::new(
+                    &mut output,
+                    &options,
+                    #[cfg(feature = "ansi_logs")]
+                    false,
+                );
+                if escaped {
+                    write_escaped_span_path(&mut serializer, path.iter().copied(), always_quote)
+                } else {
+                    write_span_path(&mut serializer, || path.iter().copied(), always_quote)
+                }
+                .unwrap();
+                output
+            };
+
+            for first in &names {
+                for second in ["a", "b c", "\\", first] {
+                    let path = [first.as_str(), second];
+                    assert_eq!(
+                        write(&path, false),
+                        write(&path, true),
+                        "{:?} {:?} {}",
+                        path,
+                        escape_mode,
+                        always_quote
+                    );
+                }
+            }
+        }
+
+        let options = SerializerOptions::default();
+        let mut output = String::new();
+        let mut serializer = Serializer::new(
+            &mut output,
+            &options,
+            #[cfg(feature = "ansi_logs")]
+            false,
+        );
+        write_span_path(&mut serializer, || ["a\\b", "c"].into_iter(), true).unwrap();
+        assert_eq!(output, r#""a\\b>c""#);
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_filtered_count() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_path(false)
+            .with_filtered_count(true)
+            .with_field_denylist(["password"])
+            .with_value_transform(|key, value| (key != "token").then_some(Cow::Borrowed(value)))
+            .capture(|| {
+                let _span = info_span!("span", password = "hunter2").entered();
+                tracing::info!(password = "hunter2", token = 42, user = "bob", "login");
+                tracing::info!(user = "bob", "logout");
+            });
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info span=span message=login user=bob fields_dropped=2\n\
+             level=info span=span message=logout user=bob\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_pretty_debug() {
+        #[derive(Debug)]
+        #[allow(dead_code)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        let capture = |builder: builder::Builder| {
+            let output = builder
+                .with_timestamp(false)
+                .with_target(false)
+                .with_pretty_debug(true)
+                .capture(|| {
+                    tracing::info!(point = ?Point { x: 1, y: 2 }, "message");
+                });
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            capture(builder::builder()),
+            "level=info message=message point=\"Point {\\n    x: 1,\\n    y: 2,\\n}\"\n"
+        );
+        assert_eq!(
+            capture(builder::builder().with_strip_newlines(true)),
+            "level=info message=message point=\"Point {␤    x: 1,␤    y: 2,␤}\"\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_line_suffix() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_line_suffix(" # replayed")
+            .capture(|| {
+                tracing::info!("a");
+                tracing::info!("b");
+            });
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info message=a # replayed\nlevel=info message=b # replayed\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_timestamp_nanos_field() {
+        let capture = |builder: builder::Builder| {
+            let output = builder
+                .with_target(false)
+                .with_clock(Arc::new(
+                    || time::macros::datetime!(2024-01-02 15:04:05.123456789 UTC),
+                ))
+                .with_timestamp_nanos_field(Some("ts_nanos".to_owned()))
+                .capture(|| {
+                    tracing::info!("message");
+                });
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            capture(builder::builder()),
+            "ts=2024-01-02T15:04:05.123456789Z ts_nanos=1704207845123456789 level=info message=message\n"
+        );
+        assert_eq!(
+            capture(builder::builder().with_timestamp(false)),
+            "ts_nanos=1704207845123456789 level=info message=message\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_additional_timestamps() {
+        use std::sync::atomic::AtomicI64;
+
+        use crate::TimestampFormat;
+
+        // a clock that moves on every reading, to check it is read once
+        let readings = AtomicI64::new(0);
+        let output = builder::builder()
+            .with_target(false)
+            .with_clock(Arc::new(move || {
+                time::macros::datetime!(2024-01-02 15:04:05.123456789 UTC)
+                    + time::Duration::seconds(readings.fetch_add(1, Ordering::Relaxed))
+            }))
+            .with_timestamp_nanos_field(Some("ts_nanos".to_owned()))
+            .with_additional_timestamp("ts_epoch", TimestampFormat::UnixMillis)
+            .with_additional_timestamp("ts_s", TimestampFormat::UnixSeconds)
+            .with_additional_timestamp("ts_us", TimestampFormat::UnixMicros)
+            .with_additional_timestamp("ts_ns", TimestampFormat::UnixNanos)
+            .with_additional_timestamp("time", TimestampFormat::Rfc3339)
+            .capture(|| {
+                tracing::info!("message");
+            });
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "ts=2024-01-02T15:04:05.123456789Z ts_nanos=1704207845123456789 \
+             ts_epoch=1704207845123 ts_s=1704207845 ts_us=1704207845123456 \
+             ts_ns=1704207845123456789 time=2024-01-02T15:04:05.123456789Z level=info \
+             message=message\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_deep_span_path() {
+        const DEPTH: usize = 3000;
 
-        subscriber::with_default(subscriber, || {
-            tracing::info!("message");
-        });
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .capture(|| {
+                let mut spans = Vec::with_capacity(DEPTH);
+                for _ in 0..DEPTH {
+                    spans.push(info_span!("s").entered());
+                }
+                tracing::info!("plain");
+                let quoted = info_span!("a b").entered();
+                tracing::info!("quoted");
 
-        let content = mock_writer.get_content();
+                // dropped from the leaf, as the registry closes the parents
+                // of the last dropped span recursively, which would
+                // overflow the stack of the test
+                drop(quoted);
+                while spans.pop().is_some() {}
+            });
+        let output = String::from_utf8(output).unwrap();
+        let mut lines = output.lines();
 
-        // assert that there is no ansi color sequences
+        let path = vec!["s"; DEPTH].join(">");
         assert_eq!(
-            content,
-            "level=info target=tracing_logfmt::formatter::tests message=message\n"
+            lines.next().unwrap(),
+            format!("level=info span_path={} message=plain", path)
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            format!("level=info span_path=\"{}>a b\" message=quoted", path)
         );
     }
 
     #[test]
-    #[cfg(feature = "ansi_logs")]
-    fn test_span_and_span_path_with_quoting() {
-        use tracing::subscriber;
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_verbosity_field() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_level_and_severity(true)
+            .with_verbosity_field(Some("v".to_owned()))
+            .capture(|| {
+                tracing::error!("a");
+                tracing::trace!("b");
+            });
 
-        let mock_writer = MockMakeWriter::new();
-        let subscriber = subscriber().with_writer(mock_writer.clone()).finish();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=error severity=3 v=1 message=a\nlevel=trace severity=7 v=5 message=b\n"
+        );
 
-        subscriber::with_default(subscriber, || {
-            let _top = info_span!("top").entered();
-            // the ' ' requires quoting
-            let _middle = info_span!("mid dle").entered();
-            let _bottom = info_span!("bottom").entered();
+        // like the severity, the verbosity is written with the level
+        let builder = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_level(false)
+            .with_verbosity_field(Some("v".to_owned()));
+        let output = builder.clone().capture(|| tracing::error!("a"));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=error v=1 message=a\n"
+        );
 
-            tracing::info!("message");
-        });
+        let output = builder
+            .with_verbosity_field(None)
+            .capture(|| tracing::error!("a"));
+        assert_eq!(String::from_utf8(output).unwrap(), "message=a\n");
+    }
 
-        let content = mock_writer.get_content();
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_dynamic_fields() {
+        thread_local! {
+            static REQUEST_ID: std::cell::Cell<u32> = const { std::cell::Cell::new(0) };
+        }
 
-        let span = make_ansi_key_value("span", "=bottom");
-        let span_path = make_ansi_key_value("span_path", "=\"top>mid dle>bottom\"");
-        let ts = make_ansi_key_value("ts", "=20");
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_static_field("service", "checkout")
+            .with_dynamic_fields(Arc::new(|| {
+                vec![(
+                    "request_id".to_owned(),
+                    REQUEST_ID.with(|id| id.get()).to_string(),
+                )]
+            }))
+            .with_dynamic_fields(Arc::new(|| {
+                vec![
+                    ("user".to_owned(), "bob smith".to_owned()),
+                    ("=".to_owned(), "invalid".to_owned()),
+                ]
+            }))
+            .capture(|| {
+                REQUEST_ID.with(|id| id.set(1));
+                tracing::info!("a");
+                REQUEST_ID.with(|id| id.set(2));
+                tracing::info!("b");
+            });
 
-        println!("{:?}", content);
-        assert!(content.contains(&span));
-        assert!(content.contains(&span_path));
-        assert!(content.contains("info"));
-        assert!(content.contains(&ts));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info service=checkout request_id=1 user=\"bob smith\" message=a\n\
+             level=info service=checkout request_id=2 user=\"bob smith\" message=b\n"
+        );
     }
+
     #[test]
-    #[cfg(feature = "ansi_logs")]
-    fn test_span_and_span_path_without_quoting() {
-        use tracing::subscriber;
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_quote_settings_apply_everywhere() {
+        let capture = |builder: builder::Builder| {
+            let output = builder
+                .with_timestamp(false)
+                .with_target(false)
+                .with_quote_char('\'')
+                .with_quote_chars(['|'])
+                .capture(|| {
+                    let _span = info_span!("a|b", "it's" = "x|y").entered();
+                    tracing::info!("message");
+                });
+            String::from_utf8(output).unwrap()
+        };
 
-        let mock_writer = MockMakeWriter::new();
-        let subscriber = subscriber().with_writer(mock_writer.clone()).finish();
+        assert_eq!(
+            capture(builder::builder()),
+            "level=info span='a|b' span_path='a|b' message=message its='x|y'\n"
+        );
+        assert_eq!(
+            capture(builder::builder().with_quoted_keys(true)),
+            "level=info span='a|b' span_path='a|b' message=message 'it\\'s'='x|y'\n"
+        );
+    }
 
-        subscriber::with_default(subscriber, || {
-            let _top = info_span!("top").entered();
-            let _middle = info_span!("middle").entered();
-            let _bottom = info_span!("bottom").entered();
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_presets() {
+        let log = || {
+            let _span = info_span!("request").entered();
+            tracing::info!("hello");
+        };
 
-            tracing::info!("message");
-        });
+        let output = builder::Builder::compact()
+            .with_timestamp(false)
+            .capture(log);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=I span=request message=hello\n"
+        );
 
-        let content = mock_writer.get_content();
+        let output = builder::Builder::verbose()
+            .with_timestamp(false)
+            .capture(log);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("seq=0 uptime_ms="), "{}", output);
+        assert!(
+            output.contains(" level=info severity=6 kind=event name=\"event src/formatter.rs:"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains(
+                " target=tracing_logfmt::formatter::tests crate=tracing_logfmt \
+                 location=src/formatter.rs:"
+            ),
+            "{}",
+            output
+        );
+        assert!(output.contains(" caller=src/formatter.rs:"), "{}", output);
+        assert!(
+            output.ends_with(
+                " module_path=tracing_logfmt::formatter::tests span=request \
+                 span_path=request message=hello span_fields=0\n"
+            ),
+            "{}",
+            output
+        );
+    }
 
-        let span = make_ansi_key_value("span", "=bottom");
-        let span_path = make_ansi_key_value("span_path", "=top>middle>bottom");
-        let ts = make_ansi_key_value("ts", "=20");
+    #[test]
+    fn test_fields_formatter_leading_separator() {
+        use tracing::subscriber;
 
-        println!("{}", content);
-        assert!(content.contains(&span));
-        assert!(content.contains(&span_path));
-        assert!(content.contains("info"));
-        assert!(content.contains(&ts));
+        // the compact formatter already writes a space after the level
+        for (leading_separator, expected_output) in [
+            (false, " INFO message=hello a=1\n"),
+            (true, " INFO  message=hello a=1\n"),
+        ] {
+            let mock_writer = MockMakeWriter::new();
+            let subscriber = tracing_subscriber::fmt()
+                .event_format(
+                    tracing_subscriber::fmt::format()
+                        .without_time()
+                        .with_target(false)
+                        .with_ansi(false)
+                        .compact(),
+                )
+                .fmt_fields(FieldsFormatter::default().with_leading_separator(leading_separator))
+                .with_writer(mock_writer.clone())
+                .finish();
+            subscriber::with_default(subscriber, || {
+                tracing::info!(a = 1, "hello");
+            });
+            assert_eq!(mock_writer.get_content(), expected_output);
+        }
     }
 
     #[test]
-    #[cfg(feature = "ansi_logs")]
-    fn test_disable_span_and_span_path() {
-        use nu_ansi_term::Color;
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_span_fields_with_leading_separator() {
         use tracing::subscriber;
+        use tracing_subscriber::layer::SubscriberExt;
 
-        let mock_writer = MockMakeWriter::new();
-        let subscriber = builder::builder()
-            .with_span_name(false)
-            .with_span_path(false)
-            .subscriber_builder()
-            .with_writer(mock_writer.clone())
-            .finish();
+        for leading_separator in [false, true] {
+            let mock_writer = MockMakeWriter::new();
+            let (events, fields) = builder::builder()
+                .with_timestamp(false)
+                .with_target(false)
+                .with_span_path(false)
+                .build();
+            let subscriber = tracing_subscriber::registry().with(
+                tracing_subscriber::fmt::layer()
+                    .event_format(events)
+                    .fmt_fields(fields.with_leading_separator(leading_separator))
+                    .with_writer(mock_writer.clone()),
+            );
 
-        subscriber::with_default(subscriber, || {
-            let _top = info_span!("top").entered();
-            let _middle = info_span!("middle").entered();
-            let _bottom = info_span!("bottom").entered();
+            subscriber::with_default(subscriber, || {
+                let span = info_span!("span", a = 1, b = tracing::field::Empty);
+                span.record("b", 2);
+                let _span = span.entered();
+                tracing::info!("message");
+            });
 
-            tracing::info!("message");
-        });
+            assert_eq!(
+                mock_writer.get_content(),
+                "level=info span=span message=message a=1 b=2\n",
+                "{}",
+                leading_separator
+            );
+        }
+    }
 
-        let content = mock_writer.get_content();
-        let message = make_ansi_key_value("message", "=message");
-        let target = make_ansi_key_value("target", "=tracing_logfmt::formatter::tests");
-        let ts = make_ansi_key_value("ts", "=");
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_metadata_quoting() {
+        let log = || {
+            let _span = info_span!("my_span").entered();
+            tracing::info!(target: "my_crate::foo", a = "b", "message");
+        };
+        let capture = |builder: builder::Builder| {
+            String::from_utf8(
+                builder
+                    .with_timestamp(false)
+                    .with_crate_name(true)
+                    .capture(log),
+            )
+            .unwrap()
+        };
 
-        println!("{}", content);
-        assert!(!content.contains("span="));
-        assert!(!content.contains("span_path="));
-        assert!(content.contains(&Color::Green.bold().paint("info").to_string()));
-        assert!(content.contains(&ts));
-        assert!(content.contains(&target));
-        assert!(content.contains(&message));
+        assert_eq!(
+            capture(builder::builder()),
+            "level=info target=my_crate::foo crate=my_crate span=my_span span_path=my_span \
+             message=message a=b\n"
+        );
+        assert_eq!(
+            capture(builder::builder().with_quote_chars([':'])),
+            "level=info target=\"my_crate::foo\" crate=my_crate span=my_span span_path=my_span \
+             message=message a=b\n"
+        );
+        assert_eq!(
+            capture(builder::builder().with_metadata_quoting(true)),
+            "level=info target=\"my_crate::foo\" crate=\"my_crate\" span=\"my_span\" \
+             span_path=\"my_span\" message=message a=b\n"
+        );
     }
 
     #[test]
-    #[cfg(all(not(feature = "ansi_logs"), windows))]
-    fn test_enable_location() {
-        use tracing::subscriber;
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_bare_message() {
+        let builder = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_bare_message(true);
 
-        let mock_writer = MockMakeWriter::new();
-        let subscriber = builder::builder()
-            .with_location(true)
-            .subscriber_builder()
-            .with_writer(mock_writer.clone())
-            .finish();
+        let output = builder.clone().capture(|| {
+            let _span = info_span!("span", b = 2).entered();
+            tracing::info!(a = 1, "this happened");
+            tracing::info!("done");
+            tracing::info!(a = 1);
+        });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\"this happened\" level=info span=span span_path=span a=1 b=2\n\
+             done level=info span=span span_path=span b=2\n\
+             level=info span=span span_path=span a=1 b=2\n"
+        );
 
-        subscriber::with_default(subscriber, || {
-            let _top = info_span!("top").entered();
-            let _middle = info_span!("middle").entered();
-            let _bottom = info_span!("bottom").entered();
+        let output = builder
+            .with_default_message("-")
+            .with_skip_blank_message(true)
+            .capture(|| {
+                tracing::info!(a = 1, " ");
+                tracing::info!(a = 1);
+            });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "- level=info a=1\n- level=info a=1\n"
+        );
+    }
 
-            tracing::info!("message");
-        });
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_separators_with_all_flag_combinations() {
+        use crate::MetadataPosition;
 
-        let content = mock_writer.get_content();
-        let split = content.split(r"location=src\formatter.rs:").last().unwrap();
-        let line = &split[..3];
-        assert!(line.parse::<u32>().is_ok());
+        const FLAGS: usize = 8;
+        for flags in 0..1 << FLAGS {
+            let flag = |index: usize| flags & (1 << index) != 0;
+            let position = if flag(6) {
+                MetadataPosition::Suffix
+            } else {
+                MetadataPosition::Prefix
+            };
+            let output = builder::builder()
+                .with_timestamp(flag(0))
+                .with_level(flag(1))
+                .with_target(flag(2))
+                .with_span_name(flag(3))
+                .with_span_path(flag(4))
+                .with_location(flag(5))
+                .with_metadata_position(position)
+                .with_bare_message(flag(7))
+                .capture(|| {
+                    let _span = info_span!("span", b = 2).entered();
+                    tracing::info!(a = 1, "message");
+                    tracing::info!(a = 1);
+                    tracing::info!(parent: None, "message");
+                    tracing::info!(parent: None, a = 1);
+                });
+            let output = String::from_utf8(output).unwrap();
 
-        println!("{}", content);
-        assert!(content.contains(r"location=src\formatter.rs:"));
-        assert!(content.contains("info"));
+            let metadata = (0..=5).filter(|&index| flag(index)).count();
+            let span_metadata = usize::from(flag(3)) + usize::from(flag(4));
+            // the message, or bare message, `a` and the span's `b`
+            let expected_entries = [
+                metadata + 3,
+                metadata + 2,
+                metadata - span_metadata + 1,
+                metadata - span_metadata + 1,
+            ];
+
+            for (line, expected_entries) in output.lines().zip(expected_entries) {
+                assert!(
+                    !line.is_empty()
+                        && !line.starts_with(' ')
+                        && !line.ends_with(' ')
+                        && !line.contains("  "),
+                    "{:b}: {:?}",
+                    flags,
+                    line
+                );
+                assert_eq!(
+                    line.split(' ').count(),
+                    expected_entries,
+                    "{:b}: {:?}",
+                    flags,
+                    line
+                );
+            }
+            assert_eq!(output.lines().count(), 4, "{:b}", flags);
+        }
     }
 
     #[test]
-    #[cfg(all(not(feature = "ansi_logs"), not(windows)))]
-    fn test_enable_location() {
-        use tracing::subscriber;
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_field_sort() {
+        let house_order = |a: &str, b: &str| {
+            let rank = |key| match key {
+                "message" => 0,
+                "request_id" => 1,
+                _ => 2,
+            };
+            rank(a).cmp(&rank(b)).then_with(|| a.cmp(b))
+        };
+        let log = || {
+            let _span = info_span!("span", a = 0).entered();
+            tracing::info!(
+                c = 3,
+                request_id = 7,
+                error = "oops",
+                z = 26,
+                b = 2,
+                "message"
+            );
+        };
+        let builder = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path(false)
+            .with_field_sort(house_order);
 
-        let mock_writer = MockMakeWriter::new();
-        let subscriber = builder::builder()
-            .with_location(true)
-            .subscriber_builder()
-            .with_writer(mock_writer.clone())
-            .finish();
+        assert_eq!(
+            String::from_utf8(builder.clone().capture(log)).unwrap(),
+            "level=info message=message request_id=7 b=2 c=3 error=oops z=26 a=0\n"
+        );
+        assert_eq!(
+            String::from_utf8(builder.with_field_grouping(true).capture(log)).unwrap(),
+            "level=info message=message request_id=7 b=2 c=3 z=26 error=oops a=0\n"
+        );
+    }
 
-        subscriber::with_default(subscriber, || {
-            let _top = info_span!("top").entered();
-            let _middle = info_span!("middle").entered();
-            let _bottom = info_span!("bottom").entered();
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_key_case() {
+        use crate::KeyCase;
 
-            tracing::info!("message");
-        });
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_key_case(KeyCase::Kebab)
+            .capture(|| {
+                let _span = info_span!("span", spanField = 1).entered();
+                tracing::info!(requestId = 2, user_name = "bob", "message");
+            });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info span=span span-path=span message=message request-id=2 user-name=bob \
+             span-field=1\n"
+        );
+    }
 
-        let content = mock_writer.get_content();
-        let split = content.split("location=src/formatter.rs:").last().unwrap();
-        let line = &split[..3];
-        assert!(line.parse::<u32>().is_ok());
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_trim_values() {
+        let log = || {
+            let _span = info_span!("span", a = " x ").entered();
+            tracing::info!(msg = "hello ", b = %"\ty z\n", c = 1, "  message");
+        };
+        let builder = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path(false);
 
-        println!("{}", content);
-        assert!(content.contains("location=src/formatter.rs:"));
-        assert!(content.contains("info"));
+        assert_eq!(
+            String::from_utf8(builder.clone().capture(log)).unwrap(),
+            "level=info message=\"  message\" msg=\"hello \" b=\"\\ty z\\n\" c=1 a=\" x \"\n"
+        );
+        assert_eq!(
+            String::from_utf8(builder.with_trim_values(true).capture(log)).unwrap(),
+            "level=info message=message msg=hello b=\"y z\" c=1 a=x\n"
+        );
     }
 
     #[test]
     #[cfg(not(feature = "ansi_logs"))]
-    fn test_enable_module_path() {
-        use tracing::subscriber;
+    fn test_span_fields_grouped() {
+        let log = || {
+            let _outer = info_span!("request", id = 1, user = "bob").entered();
+            let _empty = info_span!("empty").entered();
+            let _inner = info_span!("db", query = "select").entered();
+            tracing::info!(id = 2, "message");
+        };
+        let builder = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path(false)
+            .with_span_fields_grouped(true);
 
-        let mock_writer = MockMakeWriter::new();
-        let subscriber = builder::builder()
-            .with_module_path(true)
-            .subscriber_builder()
-            .with_writer(mock_writer.clone())
-            .finish();
+        assert_eq!(
+            String::from_utf8(builder.clone().capture(log)).unwrap(),
+            "level=info message=message id=2 request=\"id=1 user=bob\" db=\"query=select\"\n"
+        );
+        assert_eq!(
+            String::from_utf8(builder.with_max_fields(3).capture(log)).unwrap(),
+            "level=info message=message id=2 request=\"id=1 user=bob\" fields_truncated=true\n"
+        );
+    }
 
-        subscriber::with_default(subscriber, || {
-            let _top = info_span!("top").entered();
-            let _middle = info_span!("middle").entered();
-            let _bottom = info_span!("bottom").entered();
+    #[test]
+    #[cfg(all(feature = "json", not(feature = "ansi_logs")))]
+    fn test_json_span_fields_grouped() {
+        use crate::OutputFormat;
 
-            tracing::info!("message");
-        });
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_span_name(false)
+            .with_span_path(false)
+            .with_output_format(OutputFormat::Json)
+            .with_span_fields_grouped(true)
+            .capture(|| {
+                let _span = info_span!("request", id = 1, user = "bob").entered();
+                tracing::info!("message");
+            });
+        let output = String::from_utf8(output).unwrap();
 
-        let content = mock_writer.get_content();
+        assert_eq!(
+            output,
+            "{\"level\":\"info\",\"message\":\"message\",\"request\":{\"id\":1,\"user\":\"bob\"}}\n"
+        );
+        assert!(serde_json::from_str::<serde_json::Value>(&output).is_ok());
+    }
 
-        println!("{}", content);
-        assert!(content.contains("module_path=tracing_logfmt::formatter::tests"));
-        assert!(content.contains("info"));
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_with_fields_disabled() {
+        let output = builder::builder()
+            .with_timestamp(false)
+            .with_target(false)
+            .with_default_message("-")
+            .with_bare_message(true)
+            .with_fields(false)
+            .capture(|| {
+                let _span = info_span!("span", b = 2).entered();
+                tracing::info!(a = 1, "message");
+                tracing::warn!(parent: None, a = 1);
+            });
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "level=info span=span span_path=span b=2\nlevel=warn\n"
+        );
     }
 
     #[cfg(feature = "ansi_logs")]
@@ -1,16 +1,69 @@
-use tracing::Subscriber;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::{Arc, Mutex};
+
+use tracing::{Level, Subscriber};
+#[cfg(feature = "ansi_logs")]
+use tracing_subscriber::Layer as _;
+
+#[cfg(feature = "ansi_logs")]
+use crate::{AnsiMode, LevelColors};
 use tracing_subscriber::{
-    fmt::{Layer, SubscriberBuilder},
+    fmt::{Layer, MakeWriter, SubscriberBuilder},
     registry::LookupSpan,
 };
 
-use crate::{EventsFormatter, FieldsFormatter};
+use crate::serializer::SerializerOptions;
+use crate::{
+    BuiltinField, BytesEncoding, Clock, DuplicatePolicy, DurationFormat, DurationUnit, EscapeMode,
+    EventsFormatter, FieldsFormatter, FmtSpan, KeyCase, LineEnding, LogfmtConfig, MetadataPosition,
+    OutputFormat, SpanEvent, SpanRef, TimestampFormat,
+};
 
+#[derive(Clone)]
 pub struct Builder {
     events: EventsFormatter,
     fields: FieldsFormatter,
+    /// Colors are only changed based on the writer in [`AnsiMode::Auto`].
+    #[cfg(feature = "ansi_logs")]
+    ansi_mode: AnsiMode,
+    span_events: FmtSpan,
+    /// The environment is read when the formatters are built.
+    static_fields_env_prefix: Option<String>,
+}
+
+/// An invalid setting, returned by [`Builder::try_build`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuilderError {
+    /// A key set with the builder method `setting` can't be written, because
+    /// it has no characters that don't need quoting and
+    /// [`Builder::with_quoted_keys`] is not enabled.
+    InvalidKey { setting: &'static str, key: String },
+    /// The character set with [`Builder::with_quote_char`] can't be used for
+    /// quoting, because it is a letter, digit, whitespace, `=` or `\`.
+    InvalidQuoteChar(char),
 }
 
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuilderError::InvalidKey { setting, key } => {
+                write!(f, "invalid key {:?} set with `{}`", key, setting)
+            }
+            BuilderError::InvalidQuoteChar(ch) => {
+                write!(f, "invalid quote char {:?}", ch)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuilderError {}
+
 /// Create a builder that can be used to configure the formatter.
 ///
 /// Example:
@@ -29,34 +82,1016 @@ pub fn builder() -> Builder {
     Builder::new()
 }
 
+impl EventsFormatter {
+    /// Create a builder that can be used to configure the formatter, same as
+    /// [`builder()`].
+    ///
+    /// Example:
+    /// ```rust
+    /// use tracing_logfmt::EventsFormatter;
+    /// use tracing_subscriber::layer::SubscriberExt;
+    /// use tracing_subscriber::Registry;
+    ///
+    /// let subscriber = Registry::default()
+    ///     .with(EventsFormatter::builder().with_span_path(false).layer());
+    ///
+    /// tracing::subscriber::with_default(subscriber, || {
+    ///     tracing::info!("hello");
+    /// });
+    /// ```
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+}
+
 impl Builder {
     pub fn new() -> Self {
         Self {
             events: EventsFormatter::default(),
             fields: FieldsFormatter::default(),
+            #[cfg(feature = "ansi_logs")]
+            ansi_mode: AnsiMode::Auto,
+            span_events: FmtSpan::NONE,
+            static_fields_env_prefix: None,
+        }
+    }
+
+    /// Creates a builder for short lines, with:
+    /// - [`with_target(false)`](Builder::with_target)
+    /// - [`with_span_path(false)`](Builder::with_span_path)
+    /// - [`with_location(false)`](Builder::with_location)
+    /// - [`with_short_level(true)`](Builder::with_short_level)
+    ///
+    /// and the defaults for everything else, which gives lines like
+    /// `ts=2024-01-02T03:04:05Z level=I span=request message=hello`.
+    pub fn compact() -> Self {
+        Self::new()
+            .with_target(false)
+            .with_span_path(false)
+            .with_location(false)
+            .with_short_level(true)
+    }
+
+    /// Creates a builder that writes all the metadata of an event, with:
+    /// - [`with_level_and_severity(true)`](Builder::with_level_and_severity)
+    /// - [`with_kind(true)`](Builder::with_kind)
+    /// - [`with_event_name(true)`](Builder::with_event_name)
+    /// - [`with_target(true)`](Builder::with_target)
+    /// - [`with_crate_name(true)`](Builder::with_crate_name)
+    /// - [`with_module_path(true)`](Builder::with_module_path)
+    /// - [`with_location(true)`](Builder::with_location), the file and line
+    /// - [`with_caller(true)`](Builder::with_caller)
+    /// - [`with_span_name(true)`](Builder::with_span_name)
+    /// - [`with_span_path(true)`](Builder::with_span_path)
+    /// - [`with_span_field_count(true)`](Builder::with_span_field_count)
+    /// - [`with_sequence_numbers(true)`](Builder::with_sequence_numbers)
+    /// - [`with_uptime(true)`](Builder::with_uptime)
+    /// - [`with_event_id(true)`](Builder::with_event_id), with the `event_id`
+    ///   feature
+    /// - [`with_otel_ids(true)`](Builder::with_otel_ids), with the `otel`
+    ///   feature
+    ///
+    /// and the defaults for everything else. Thread names and ids are not
+    /// available, there is no field for them.
+    pub fn verbose() -> Self {
+        let builder = Self::new()
+            .with_level_and_severity(true)
+            .with_kind(true)
+            .with_event_name(true)
+            .with_target(true)
+            .with_crate_name(true)
+            .with_module_path(true)
+            .with_location(true)
+            .with_caller(true)
+            .with_span_name(true)
+            .with_span_path(true)
+            .with_span_field_count(true)
+            .with_sequence_numbers(true)
+            .with_uptime(true);
+        #[cfg(feature = "event_id")]
+        let builder = builder.with_event_id(true);
+        #[cfg(feature = "otel")]
+        let builder = builder.with_otel_ids(true);
+        builder
+    }
+
+    /// Creates a builder with all settings taken from `config`.
+    ///
+    /// Settings that can't be described as data, like
+    /// [`Builder::with_clock`], can still be set on the returned builder.
+    pub fn from_config(config: LogfmtConfig) -> Self {
+        let LogfmtConfig {
+            level,
+            level_and_severity,
+            verbosity_field,
+            short_level,
+            level_key,
+            target_level_overrides,
+            kind,
+            event_name,
+            target,
+            quoted_target,
+            crate_name,
+            verbose_level,
+            span_name,
+            span_ref,
+            span_name_id_combined,
+            span_name_id_joiner,
+            span_path,
+            quoted_span_path,
+            quoted_metadata,
+            span_path_ids,
+            span_fields_once,
+            span_fields_grouped,
+            span_field_count,
+            root_span_fields,
+            max_fields,
+            location,
+            caller,
+            module_path,
+            timestamp,
+            timestamp_from_field,
+            timestamp_nanos_field,
+            additional_timestamps,
+            field_order,
+            metadata_position,
+            sequence_numbers,
+            uptime,
+            #[cfg(feature = "event_id")]
+            event_id,
+            #[cfg(feature = "otel")]
+            otel_ids,
+            duration_suffix_parsing,
+            duration_unit,
+            span_events,
+            span_duration_format,
+            duplicate_field_policy,
+            default_message,
+            skip_blank_message,
+            bare_message,
+            fields,
+            field_grouping,
+            line_ending,
+            trailing_newline,
+            line_suffix,
+            field_renames,
+            version,
+            git_sha,
+            static_fields,
+            static_fields_env_prefix,
+            promoted_span_fields,
+            field_denylist,
+            filtered_count,
+            max_value_length,
+            error_field,
+            quote_numbers,
+            skip_none_debug,
+            type_hints,
+            pretty_debug,
+            trim_values,
+            bytes_encoding,
+            dotted_fields,
+            fallback_key,
+            #[cfg(feature = "ansi_logs")]
+            ansi_mode,
+            #[cfg(feature = "ansi_logs")]
+            level_colors,
+            quoted_keys,
+            escape_mode,
+            key_case,
+            output_format,
+            quote_chars,
+            escape_equals,
+            quote_char,
+            strip_newlines,
+            newline_placeholder,
+        } = config;
+
+        let mut builder = Self::new()
+            .with_level(level)
+            .with_level_and_severity(level_and_severity)
+            .with_verbosity_field(verbosity_field)
+            .with_short_level(short_level)
+            .with_level_key(level_key)
+            .with_target_level_override(target_level_overrides)
+            .with_kind(kind)
+            .with_event_name(event_name)
+            .with_target(target)
+            .with_quoted_target(quoted_target)
+            .with_crate_name(crate_name)
+            .with_span_name(span_name)
+            .with_span_ref(span_ref)
+            .with_span_name_id_combined(span_name_id_combined)
+            .with_span_name_id_joiner(span_name_id_joiner)
+            .with_span_path(span_path)
+            .with_quoted_span_path(quoted_span_path)
+            .with_metadata_quoting(quoted_metadata)
+            .with_span_path_ids(span_path_ids)
+            .with_span_fields_once(span_fields_once)
+            .with_span_fields_grouped(span_fields_grouped)
+            .with_span_field_count(span_field_count)
+            .with_root_span_fields(root_span_fields)
+            .with_location(location)
+            .with_caller(caller)
+            .with_module_path(module_path)
+            .with_timestamp(timestamp)
+            .with_timestamp_from_field(timestamp_from_field)
+            .with_timestamp_nanos_field(timestamp_nanos_field)
+            .with_field_order(field_order)
+            .with_metadata_position(metadata_position)
+            .with_sequence_numbers(sequence_numbers)
+            .with_uptime(uptime)
+            .with_duration_suffix_parsing(duration_suffix_parsing)
+            .with_duration_unit(duration_unit)
+            .with_span_events(
+                span_events
+                    .into_iter()
+                    .fold(FmtSpan::NONE, |all, event| all | event.into()),
+            )
+            .with_duplicate_field_policy(duplicate_field_policy)
+            .with_skip_blank_message(skip_blank_message)
+            .with_bare_message(bare_message)
+            .with_fields(fields)
+            .with_field_grouping(field_grouping)
+            .with_line_ending(line_ending)
+            .with_trailing_newline(trailing_newline)
+            .with_field_denylist(field_denylist)
+            .with_filtered_count(filtered_count)
+            .with_error_field(error_field)
+            .with_quote_numbers(quote_numbers)
+            .with_skip_none_debug(skip_none_debug)
+            .with_type_hints(type_hints)
+            .with_pretty_debug(pretty_debug)
+            .with_trim_values(trim_values)
+            .with_bytes_encoding(bytes_encoding)
+            .with_dotted_fields(dotted_fields)
+            .with_quoted_keys(quoted_keys)
+            .with_escape_mode(escape_mode)
+            .with_key_case(key_case)
+            .with_output_format(output_format)
+            .with_quote_chars(quote_chars)
+            .with_escape_equals(escape_equals)
+            .with_quote_char(quote_char)
+            .with_strip_newlines(strip_newlines);
+
+        if let Some(level) = verbose_level {
+            builder = builder.with_verbose_level(level);
+        }
+        #[cfg(feature = "event_id")]
+        {
+            builder = builder.with_event_id(event_id);
+        }
+        #[cfg(feature = "otel")]
+        {
+            builder = builder.with_otel_ids(otel_ids);
+        }
+        if let Some(suffix) = line_suffix {
+            builder = builder.with_line_suffix(suffix);
+        }
+        if let Some(message) = default_message {
+            builder = builder.with_default_message(message);
+        }
+        if let Some(version) = version {
+            builder = builder.with_version(version);
+        }
+        if let Some(git_sha) = git_sha {
+            builder = builder.with_git_sha(git_sha);
+        }
+        for (key, format) in additional_timestamps {
+            builder = builder.with_additional_timestamp(key, format);
+        }
+        for (key, value) in static_fields {
+            builder = builder.with_static_field(key, value);
+        }
+        for (field_name, output_key) in promoted_span_fields {
+            builder = builder.with_promoted_span_field(field_name, output_key);
+        }
+        if let Some(prefix) = static_fields_env_prefix {
+            builder = builder.with_static_fields_from_env(&prefix);
+        }
+        for (from, to) in field_renames {
+            builder = builder.with_field_rename(from, to);
+        }
+        if let Some(max_length) = max_value_length {
+            builder = builder.with_max_value_length(max_length);
+        }
+        if let Some(key) = fallback_key {
+            builder = builder.with_fallback_key(key);
+        }
+        #[cfg(feature = "ansi_logs")]
+        {
+            builder = builder
+                .with_ansi_mode(ansi_mode)
+                .with_level_colors(level_colors);
+        }
+        if let Some(placeholder) = newline_placeholder {
+            builder = builder.with_newline_placeholder(placeholder);
+        }
+        if let Some(format) = span_duration_format {
+            builder = builder.with_span_duration_format(format);
+        }
+        if let Some(max_fields) = max_fields {
+            builder = builder.with_max_fields(max_fields);
+        }
+
+        builder
+    }
+
+    /// Returns the settings of the builder that can be described as data, to
+    /// inspect what it is configured to do without building it.
+    ///
+    /// Passing the result to [`Builder::from_config`] gives a builder with the
+    /// same settings, except for the ones that aren't part of
+    /// [`LogfmtConfig`]. A changed `field_order` is returned with all fields
+    /// listed.
+    pub fn to_config(&self) -> LogfmtConfig {
+        let mut field_denylist: Vec<_> = self.field_denylist().iter().cloned().collect();
+        field_denylist.sort();
+        let field_order = if self.field_order() == BuiltinField::DEFAULT_ORDER {
+            Vec::new()
+        } else {
+            self.field_order().to_vec()
+        };
+        let newline_placeholder = Some(self.newline_placeholder())
+            .filter(|placeholder| *placeholder != SerializerOptions::default().newline_placeholder)
+            .map(str::to_owned);
+
+        LogfmtConfig {
+            level: self.level_enabled(),
+            level_and_severity: self.level_and_severity_enabled(),
+            verbosity_field: self.verbosity_field().map(str::to_owned),
+            short_level: self.short_level_enabled(),
+            level_key: self.level_key().to_owned(),
+            target_level_overrides: self.target_level_overrides().clone(),
+            kind: self.kind_enabled(),
+            event_name: self.event_name_enabled(),
+            target: self.target_enabled(),
+            quoted_target: self.quoted_target_enabled(),
+            crate_name: self.crate_name_enabled(),
+            verbose_level: self.verbose_level(),
+            span_name: self.span_name_enabled(),
+            span_ref: self.span_ref(),
+            span_name_id_combined: self.span_name_id_combined_enabled(),
+            span_name_id_joiner: self.span_name_id_joiner(),
+            span_path: self.span_path_enabled(),
+            quoted_span_path: self.quoted_span_path_enabled(),
+            quoted_metadata: self.metadata_quoting_enabled(),
+            span_path_ids: self.span_path_ids_enabled(),
+            span_fields_once: self.span_fields_once_enabled(),
+            span_fields_grouped: self.span_fields_grouped_enabled(),
+            span_field_count: self.span_field_count_enabled(),
+            root_span_fields: self.root_span_fields_enabled(),
+            max_fields: self.max_fields(),
+            location: self.location_enabled(),
+            caller: self.caller_enabled(),
+            module_path: self.module_path_enabled(),
+            timestamp: self.timestamp_enabled(),
+            timestamp_from_field: self.timestamp_from_field().map(str::to_owned),
+            timestamp_nanos_field: self.timestamp_nanos_field().map(str::to_owned),
+            additional_timestamps: self.additional_timestamps().to_vec(),
+            field_order,
+            metadata_position: self.metadata_position(),
+            sequence_numbers: self.sequence_numbers_enabled(),
+            uptime: self.uptime_enabled(),
+            #[cfg(feature = "event_id")]
+            event_id: self.event_id_enabled(),
+            #[cfg(feature = "otel")]
+            otel_ids: self.otel_ids_enabled(),
+            duration_suffix_parsing: self.duration_suffix_parsing_enabled(),
+            duration_unit: self.duration_unit(),
+            span_events: SpanEvent::from_fmt_span(self.span_events()),
+            span_duration_format: self.span_duration_format(),
+            duplicate_field_policy: self.duplicate_field_policy(),
+            default_message: self.default_message().map(str::to_owned),
+            skip_blank_message: self.skip_blank_message_enabled(),
+            bare_message: self.bare_message_enabled(),
+            fields: self.fields_enabled(),
+            field_grouping: self.field_grouping_enabled(),
+            line_ending: self.line_ending(),
+            trailing_newline: self.trailing_newline_enabled(),
+            line_suffix: self.line_suffix().map(str::to_owned),
+            field_renames: self.field_renames().clone(),
+            version: self.version().map(str::to_owned),
+            git_sha: self.git_sha().map(str::to_owned),
+            static_fields: self.static_fields().to_vec(),
+            static_fields_env_prefix: self.static_fields_env_prefix().map(str::to_owned),
+            promoted_span_fields: self.promoted_span_fields().to_vec(),
+            field_denylist,
+            filtered_count: self.filtered_count_enabled(),
+            max_value_length: self.max_value_length(),
+            error_field: self.error_field_enabled(),
+            quote_numbers: self.quote_numbers_enabled(),
+            skip_none_debug: self.skip_none_debug_enabled(),
+            type_hints: self.type_hints_enabled(),
+            pretty_debug: self.pretty_debug_enabled(),
+            trim_values: self.trim_values_enabled(),
+            bytes_encoding: self.bytes_encoding(),
+            dotted_fields: self.dotted_fields_enabled(),
+            fallback_key: self.fallback_key().map(str::to_owned),
+            #[cfg(feature = "ansi_logs")]
+            ansi_mode: self.ansi_mode(),
+            #[cfg(feature = "ansi_logs")]
+            level_colors: self.level_colors(),
+            quoted_keys: self.quoted_keys_enabled(),
+            escape_mode: self.escape_mode(),
+            key_case: self.key_case(),
+            output_format: self.output_format(),
+            quote_chars: self.quote_chars().to_vec(),
+            escape_equals: self.escape_equals_enabled(),
+            quote_char: self.quote_char(),
+            strip_newlines: self.strip_newlines_enabled(),
+            newline_placeholder,
+        }
+    }
+
+    /// The setting of [`Builder::with_level`].
+    pub fn level_enabled(&self) -> bool {
+        self.events.with_level
+    }
+    /// The setting of [`Builder::with_short_level`].
+    pub fn short_level_enabled(&self) -> bool {
+        self.events.with_short_level
+    }
+    /// The setting of [`Builder::with_level_key`].
+    pub fn level_key(&self) -> &str {
+        &self.events.level_key
+    }
+    /// The setting of [`Builder::with_target_level_override`].
+    pub fn target_level_overrides(&self) -> &HashMap<String, Level> {
+        &self.events.target_level_overrides
+    }
+    /// The setting of [`Builder::with_level_and_severity`].
+    pub fn level_and_severity_enabled(&self) -> bool {
+        self.events.with_level_and_severity
+    }
+    /// The setting of [`Builder::with_verbosity_field`].
+    pub fn verbosity_field(&self) -> Option<&str> {
+        self.events.verbosity_field.as_deref()
+    }
+    /// The setting of [`Builder::with_kind`].
+    pub fn kind_enabled(&self) -> bool {
+        self.events.with_kind
+    }
+    /// The setting of [`Builder::with_event_name`].
+    pub fn event_name_enabled(&self) -> bool {
+        self.events.with_event_name
+    }
+    /// The setting of [`Builder::with_target`].
+    pub fn target_enabled(&self) -> bool {
+        self.events.with_target
+    }
+    /// The setting of [`Builder::with_quoted_target`].
+    pub fn quoted_target_enabled(&self) -> bool {
+        self.events.with_quoted_target
+    }
+    /// The setting of [`Builder::with_crate_name`].
+    pub fn crate_name_enabled(&self) -> bool {
+        self.events.with_crate_name
+    }
+    /// The setting of [`Builder::with_verbose_level`].
+    pub fn verbose_level(&self) -> Option<Level> {
+        self.events.verbose_level
+    }
+    /// The setting of [`Builder::with_span_name`].
+    pub fn span_name_enabled(&self) -> bool {
+        self.events.with_span_name
+    }
+    /// The setting of [`Builder::with_span_ref`].
+    pub fn span_ref(&self) -> SpanRef {
+        self.events.span_ref
+    }
+    /// The setting of [`Builder::with_span_name_id_combined`].
+    pub fn span_name_id_combined_enabled(&self) -> bool {
+        self.events.with_span_name_id_combined
+    }
+    /// The setting of [`Builder::with_span_name_id_joiner`].
+    pub fn span_name_id_joiner(&self) -> char {
+        self.events.span_name_id_joiner
+    }
+    /// The setting of [`Builder::with_span_path`].
+    pub fn span_path_enabled(&self) -> bool {
+        self.events.with_span_path
+    }
+    /// The setting of [`Builder::with_quoted_span_path`].
+    pub fn quoted_span_path_enabled(&self) -> bool {
+        self.events.with_quoted_span_path
+    }
+    /// The setting of [`Builder::with_metadata_quoting`].
+    pub fn metadata_quoting_enabled(&self) -> bool {
+        self.events.with_quoted_metadata
+    }
+    /// The setting of [`Builder::with_span_path_ids`].
+    pub fn span_path_ids_enabled(&self) -> bool {
+        self.events.with_span_path_ids
+    }
+    /// The setting of [`Builder::with_span_fields_once`].
+    pub fn span_fields_once_enabled(&self) -> bool {
+        self.events.with_span_fields_once
+    }
+    /// The setting of [`Builder::with_span_fields_grouped`].
+    pub fn span_fields_grouped_enabled(&self) -> bool {
+        self.events.with_span_fields_grouped
+    }
+    /// The setting of [`Builder::with_span_field_count`].
+    pub fn span_field_count_enabled(&self) -> bool {
+        self.events.with_span_field_count
+    }
+    /// The setting of [`Builder::with_root_span_fields`].
+    pub fn root_span_fields_enabled(&self) -> bool {
+        self.events.with_root_span_fields
+    }
+    /// The setting of [`Builder::with_max_fields`].
+    pub fn max_fields(&self) -> Option<usize> {
+        self.events.max_fields
+    }
+    /// The setting of [`Builder::with_location`].
+    pub fn location_enabled(&self) -> bool {
+        self.events.with_location
+    }
+    /// The setting of [`Builder::with_caller`].
+    pub fn caller_enabled(&self) -> bool {
+        self.events.with_caller
+    }
+    /// The setting of [`Builder::with_module_path`].
+    pub fn module_path_enabled(&self) -> bool {
+        self.events.with_module_path
+    }
+    /// The setting of [`Builder::with_timestamp`].
+    pub fn timestamp_enabled(&self) -> bool {
+        self.events.with_timestamp
+    }
+    /// The setting of [`Builder::with_clock`].
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.events.clock
+    }
+    /// The setting of [`Builder::with_timestamp_from_field`].
+    pub fn timestamp_from_field(&self) -> Option<&str> {
+        self.events.timestamp_from_field.as_deref()
+    }
+    /// The setting of [`Builder::with_timestamp_nanos_field`].
+    pub fn timestamp_nanos_field(&self) -> Option<&str> {
+        self.events.timestamp_nanos_field.as_deref()
+    }
+    /// The setting of [`Builder::with_additional_timestamp`].
+    pub fn additional_timestamps(&self) -> &[(String, TimestampFormat)] {
+        &self.events.additional_timestamps
+    }
+    /// The setting of [`Builder::with_field_order`], with all fields listed.
+    pub fn field_order(&self) -> &[BuiltinField] {
+        &self.events.field_order
+    }
+    /// The setting of [`Builder::with_metadata_position`].
+    pub fn metadata_position(&self) -> MetadataPosition {
+        self.events.metadata_position
+    }
+    /// The setting of [`Builder::with_sequence_numbers`].
+    pub fn sequence_numbers_enabled(&self) -> bool {
+        self.events.with_sequence_numbers
+    }
+    /// The setting of [`Builder::with_uptime`].
+    pub fn uptime_enabled(&self) -> bool {
+        self.events.with_uptime
+    }
+    /// The setting of [`Builder::with_event_id`].
+    #[cfg(feature = "event_id")]
+    pub fn event_id_enabled(&self) -> bool {
+        self.events.with_event_id
+    }
+    /// The setting of [`Builder::with_otel_ids`].
+    #[cfg(feature = "otel")]
+    pub fn otel_ids_enabled(&self) -> bool {
+        self.events.with_otel_ids
+    }
+    /// The setting of [`Builder::with_duration_suffix_parsing`].
+    pub fn duration_suffix_parsing_enabled(&self) -> bool {
+        self.events.with_duration_suffix_parsing
+    }
+    /// The setting of [`Builder::with_duration_unit`].
+    pub fn duration_unit(&self) -> DurationUnit {
+        self.events.duration_unit
+    }
+    /// The setting of [`Builder::with_span_events`].
+    pub fn span_events(&self) -> FmtSpan {
+        self.span_events.clone()
+    }
+    /// The setting of [`Builder::with_span_duration_format`].
+    pub fn span_duration_format(&self) -> Option<DurationFormat> {
+        self.events.span_duration_format
+    }
+    /// The setting of [`Builder::with_duplicate_field_policy`].
+    pub fn duplicate_field_policy(&self) -> DuplicatePolicy {
+        self.events.duplicate_policy
+    }
+    /// The setting of [`Builder::with_default_message`].
+    pub fn default_message(&self) -> Option<&str> {
+        self.events.default_message.as_deref()
+    }
+    /// The setting of [`Builder::with_skip_blank_message`].
+    pub fn skip_blank_message_enabled(&self) -> bool {
+        self.events.with_skip_blank_message
+    }
+    /// The setting of [`Builder::with_bare_message`].
+    pub fn bare_message_enabled(&self) -> bool {
+        self.events.with_bare_message
+    }
+    /// The setting of [`Builder::with_fields`].
+    pub fn fields_enabled(&self) -> bool {
+        self.events.with_fields
+    }
+    /// The setting of [`Builder::with_field_grouping`].
+    pub fn field_grouping_enabled(&self) -> bool {
+        self.events.with_field_grouping
+    }
+    /// The setting of [`Builder::with_line_ending`].
+    pub fn line_ending(&self) -> LineEnding {
+        self.events.line_ending
+    }
+    /// The setting of [`Builder::with_trailing_newline`].
+    pub fn trailing_newline_enabled(&self) -> bool {
+        self.events.with_trailing_newline
+    }
+    /// The setting of [`Builder::with_line_suffix`].
+    pub fn line_suffix(&self) -> Option<&str> {
+        self.events.line_suffix.as_deref()
+    }
+    /// The setting of [`Builder::with_version`].
+    pub fn version(&self) -> Option<&str> {
+        self.events.version.as_deref()
+    }
+    /// The setting of [`Builder::with_git_sha`].
+    pub fn git_sha(&self) -> Option<&str> {
+        self.events.git_sha.as_deref()
+    }
+    /// The setting of [`Builder::with_static_field`].
+    pub fn static_fields(&self) -> &[(String, String)] {
+        &self.events.static_fields
+    }
+    /// The setting of [`Builder::with_static_fields_from_env`].
+    pub fn static_fields_env_prefix(&self) -> Option<&str> {
+        self.static_fields_env_prefix.as_deref()
+    }
+    /// The setting of [`Builder::with_promoted_span_field`].
+    pub fn promoted_span_fields(&self) -> &[(String, String)] {
+        &self.events.promoted_span_fields
+    }
+    /// The setting of [`Builder::with_field_rename`].
+    pub fn field_renames(&self) -> &HashMap<String, String> {
+        &self.events.field_options.renames
+    }
+    /// The setting of [`Builder::with_field_denylist`].
+    pub fn field_denylist(&self) -> &HashSet<String> {
+        &self.events.field_options.denylist
+    }
+    /// The setting of [`Builder::with_filtered_count`].
+    pub fn filtered_count_enabled(&self) -> bool {
+        self.events.with_filtered_count
+    }
+    /// The setting of [`Builder::with_max_value_length`].
+    pub fn max_value_length(&self) -> Option<usize> {
+        self.events.field_options.max_value_length
+    }
+    /// The setting of [`Builder::with_error_field`].
+    pub fn error_field_enabled(&self) -> bool {
+        self.events.field_options.with_error_field
+    }
+    /// The setting of [`Builder::with_fallback_key`].
+    pub fn fallback_key(&self) -> Option<&str> {
+        self.events.field_options.fallback_key.as_deref()
+    }
+    /// The setting of [`Builder::with_quote_numbers`].
+    pub fn quote_numbers_enabled(&self) -> bool {
+        self.events.field_options.quote_numbers
+    }
+    /// The setting of [`Builder::with_skip_none_debug`].
+    pub fn skip_none_debug_enabled(&self) -> bool {
+        self.events.field_options.skip_none_debug
+    }
+    /// The setting of [`Builder::with_type_hints`].
+    pub fn type_hints_enabled(&self) -> bool {
+        self.events.field_options.type_hints
+    }
+    /// The setting of [`Builder::with_pretty_debug`].
+    pub fn pretty_debug_enabled(&self) -> bool {
+        self.events.field_options.pretty_debug
+    }
+    /// The setting of [`Builder::with_trim_values`].
+    pub fn trim_values_enabled(&self) -> bool {
+        self.events.field_options.trim_values
+    }
+    /// The setting of [`Builder::with_bytes_encoding`].
+    pub fn bytes_encoding(&self) -> BytesEncoding {
+        self.events.field_options.bytes_encoding
+    }
+    /// The setting of [`Builder::with_dotted_fields`].
+    pub fn dotted_fields_enabled(&self) -> bool {
+        self.events.field_options.dotted_fields
+    }
+    /// Whether ANSI colors are used, as decided by [`Builder::with_ansi_color`]
+    /// or [`Builder::with_ansi_mode`].
+    #[cfg(feature = "ansi_logs")]
+    pub fn ansi_color_enabled(&self) -> bool {
+        self.events.with_ansi_color
+    }
+    /// The setting of [`Builder::with_ansi_mode`].
+    #[cfg(feature = "ansi_logs")]
+    pub fn ansi_mode(&self) -> AnsiMode {
+        self.ansi_mode
+    }
+    /// The setting of [`Builder::with_level_colors`].
+    #[cfg(feature = "ansi_logs")]
+    pub fn level_colors(&self) -> LevelColors {
+        self.events.level_colors
+    }
+    /// The setting of [`Builder::with_quoted_keys`].
+    pub fn quoted_keys_enabled(&self) -> bool {
+        self.events.serializer.quote_keys
+    }
+    /// The setting of [`Builder::with_output_format`].
+    pub fn output_format(&self) -> OutputFormat {
+        self.events.serializer.output_format
+    }
+    /// The setting of [`Builder::with_escape_mode`].
+    pub fn escape_mode(&self) -> EscapeMode {
+        self.events.serializer.escape_mode
+    }
+    /// The setting of [`Builder::with_key_case`].
+    pub fn key_case(&self) -> KeyCase {
+        self.events.serializer.key_case
+    }
+    /// The setting of [`Builder::with_quote_chars`].
+    pub fn quote_chars(&self) -> &[char] {
+        &self.events.serializer.quote_chars
+    }
+    /// The setting of [`Builder::with_escape_equals`].
+    pub fn escape_equals_enabled(&self) -> bool {
+        self.events.serializer.escape_equals
+    }
+    /// The setting of [`Builder::with_quote_char`].
+    pub fn quote_char(&self) -> char {
+        self.events.serializer.quote_char
+    }
+    /// The setting of [`Builder::with_strip_newlines`].
+    pub fn strip_newlines_enabled(&self) -> bool {
+        self.events.serializer.strip_newlines
+    }
+    /// The setting of [`Builder::with_newline_placeholder`].
+    pub fn newline_placeholder(&self) -> &str {
+        &self.events.serializer.newline_placeholder
+    }
+
+    /// Turn off all builtin metadata fields, i.e. everything but `ts` and
+    /// the fields of the event and its spans. Fields can be turned on again
+    /// individually afterwards, e.g. `without_metadata().with_level(true)`.
+    pub fn without_metadata(mut self) -> Self {
+        let events = &mut self.events;
+        events.with_level = false;
+        events.with_level_and_severity = false;
+        events.verbosity_field = None;
+        events.with_kind = false;
+        events.with_event_name = false;
+        events.with_target = false;
+        events.with_crate_name = false;
+        events.with_location = false;
+        events.with_caller = false;
+        events.with_module_path = false;
+        events.with_span_name = false;
+        events.with_span_path = false;
+        events.with_span_field_count = false;
+        events.with_sequence_numbers = false;
+        events.with_uptime = false;
+        #[cfg(feature = "event_id")]
+        {
+            events.with_event_id = false;
         }
+        #[cfg(feature = "otel")]
+        {
+            events.with_otel_ids = false;
+        }
+        self
     }
 
     pub fn with_level(mut self, enable: bool) -> Self {
         self.events.with_level = enable;
         self
     }
+    /// Write the level as a single uppercase letter, `E`, `W`, `I`, `D` or
+    /// `T`, instead of its name. Only changes the `level` value, the
+    /// `severity` field and any extra level fields are written as before.
+    pub fn with_short_level(mut self, enable: bool) -> Self {
+        self.events.with_short_level = enable;
+        self
+    }
+    /// Sets the key the level is written with. Defaults to `level`.
+    pub fn with_level_key(mut self, key: impl Into<String>) -> Self {
+        self.events.level_key = key.into();
+        self
+    }
+    /// Use the key names that Grafana's logfmt parser recognizes: `lvl` for
+    /// the level and `msg` for the message. The timestamp is always written
+    /// in RFC 3339, which Grafana expects.
+    pub fn with_grafana_defaults(self) -> Self {
+        self.with_level_key("lvl")
+            .with_field_rename("message", "msg")
+    }
+    /// Write a different level in the `level` field for events from these
+    /// targets, e.g. to make a noisy dependency's `info` logs show up as
+    /// `debug`. A target also matches its child modules, and the closest
+    /// match wins. Only the written label changes; filtering, the `severity`
+    /// field and extra level fields still use the real level.
+    pub fn with_target_level_override<K>(
+        mut self,
+        overrides: impl IntoIterator<Item = (K, Level)>,
+    ) -> Self
+    where
+        K: Into<String>,
+    {
+        self.events.target_level_overrides.extend(
+            overrides
+                .into_iter()
+                .map(|(target, level)| (target.into(), level)),
+        );
+        self
+    }
+    /// Emit a numeric syslog `severity` field next to the `level` field,
+    /// e.g. `level=info severity=6`. The `level` field is written whenever
+    /// this is enabled, even if [`Builder::with_level`] is disabled.
+    pub fn with_level_and_severity(mut self, enable: bool) -> Self {
+        self.events.with_level_and_severity = enable;
+        self
+    }
+    /// Emit a numeric verbosity field with the given key next to the `level`
+    /// field, from `1` for error to `5` for trace, e.g. `level=trace v=5`.
+    /// This is the opposite order of the syslog `severity`, and can be used
+    /// with or without it. `None`, the default, leaves the field out.
+    ///
+    /// Like [`Builder::with_level_and_severity`], the `level` field is written
+    /// whenever this is set, even if [`Builder::with_level`] is disabled.
+    pub fn with_verbosity_field(mut self, key: Option<String>) -> Self {
+        self.events.verbosity_field = key;
+        self
+    }
+    /// Emit another field with a representation of the level, after the
+    /// `level` field, e.g. `level=info level_num=6` with a function that maps
+    /// the level to a number. Can be used several times to add more fields.
+    ///
+    /// The fields are only written when the `level` field is. The function is
+    /// shared by all threads that log, so it must be `Send + Sync`.
+    pub fn with_extra_level_field<F>(mut self, key: impl Into<String>, format_level: F) -> Self
+    where
+        F: Fn(Level) -> String + Send + Sync + 'static,
+    {
+        self.events
+            .extra_level_fields
+            .push((key.into(), Arc::new(format_level)));
+        self
+    }
+    /// Emit a `kind` field that is `span` for span lifecycle events (see
+    /// [`Builder::with_span_events`]) and `event` for all other events.
+    pub fn with_kind(mut self, enable: bool) -> Self {
+        self.events.with_kind = enable;
+        self
+    }
+    /// Emit the name of the event's metadata as `name`. Events without an
+    /// explicit `name:` get a generated one like `event src/main.rs:10`.
+    pub fn with_event_name(mut self, enable: bool) -> Self {
+        self.events.with_event_name = enable;
+        self
+    }
     pub fn with_target(mut self, enable: bool) -> Self {
         self.events.with_target = enable;
         self
     }
+    /// Always quote the value of the `target` field, e.g.
+    /// `target="my_crate::foo<Bar>"`, even if it would not need quoting.
+    pub fn with_quoted_target(mut self, enable: bool) -> Self {
+        self.events.with_quoted_target = enable;
+        self
+    }
+    /// Emit a `crate` field with the first path segment of the target, e.g.
+    /// `crate=my_crate` for the target `my_crate::foo`. Left out for events
+    /// with an empty target.
+    pub fn with_crate_name(mut self, enable: bool) -> Self {
+        self.events.with_crate_name = enable;
+        self
+    }
+    /// Only write the `target`, `location`, `caller` and `span_path` fields for
+    /// events at `level` or more verbose, e.g. with [`Level::DEBUG`] they are
+    /// written for `DEBUG` and `TRACE` events but left out at `INFO` and above.
+    ///
+    /// Each field is still only written if it is enabled.
+    pub fn with_verbose_level(mut self, level: Level) -> Self {
+        self.events.verbose_level = Some(level);
+        self
+    }
     pub fn with_span_name(mut self, enable: bool) -> Self {
         self.events.with_span_name = enable;
         self
     }
+    /// Selects which span is reported in the `span` field. Defaults to
+    /// [`SpanRef::Leaf`]; [`SpanRef::Root`] reports the outermost span instead,
+    /// which is useful when that span carries the request being handled.
+    pub fn with_span_ref(mut self, span_ref: SpanRef) -> Self {
+        self.events.span_ref = span_ref;
+        self
+    }
+    /// Append the id of the span to its name in the `span` field, e.g.
+    /// `span=request#42`, to tell apart spans with the same name. The value
+    /// is quoted if the name or joiner need it. Defaults to `false`.
+    pub fn with_span_name_id_combined(mut self, enable: bool) -> Self {
+        self.events.with_span_name_id_combined = enable;
+        self
+    }
+    /// Sets the character between the span name and id when
+    /// [`Builder::with_span_name_id_combined`] is enabled. Defaults to `#`.
+    pub fn with_span_name_id_joiner(mut self, joiner: char) -> Self {
+        self.events.span_name_id_joiner = joiner;
+        self
+    }
     pub fn with_span_path(mut self, enable: bool) -> Self {
         self.events.with_span_path = enable;
         self
     }
+
+    /// Always quotes the `span_path` value, not only when one of the span
+    /// names needs it. Defaults to `false`.
+    pub fn with_quoted_span_path(mut self, enable: bool) -> Self {
+        self.events.with_quoted_span_path = enable;
+        self
+    }
+    /// Always quote the values taken from the metadata: `target`, `crate`,
+    /// `module_path`, `span` and `span_path`. By default they are quoted only
+    /// when they contain a character that needs it, with the same rules as
+    /// any other value, so e.g. `target=my_crate::foo` stays unquoted but
+    /// would be quoted if `:` is added with [`Builder::with_quote_chars`].
+    /// Defaults to `false`.
+    ///
+    /// [`Builder::with_quoted_target`] and [`Builder::with_quoted_span_path`]
+    /// do the same for a single field.
+    pub fn with_metadata_quoting(mut self, enable: bool) -> Self {
+        self.events.with_quoted_metadata = enable;
+        self
+    }
+    /// Build the `span_path` from the span ids instead of the names, e.g.
+    /// `span_path=3>7>12`, for when span names collide. Defaults to `false`.
+    pub fn with_span_path_ids(mut self, enable: bool) -> Self {
+        self.events.with_span_path_ids = enable;
+        self
+    }
+    /// Only write the fields of a span on the first event within it. Later
+    /// events leave them out, but still have the `span` and `span_path`
+    /// fields, so the fields can be found on an earlier line.
+    ///
+    /// Fields recorded on a span after its first event are not written.
+    /// Fields cut off by [`Builder::with_max_fields`] are written with the
+    /// next event, and the ones that were written are not repeated.
+    pub fn with_span_fields_once(mut self, enable: bool) -> Self {
+        self.events.with_span_fields_once = enable;
+        self
+    }
+    /// Write the fields of each span as a single value with the name of the
+    /// span as key, e.g. `request="id=1 user=bob"`, instead of merging them
+    /// with the fields of the event. With JSON output the fields are written
+    /// as an object, e.g. `"request":{"id":1,"user":"bob"}`. Spans without
+    /// fields are left out. Defaults to `false`.
+    ///
+    /// Nested spans with the same name give the same key more than once.
+    /// With [`Builder::with_max_fields`], each span counts as one field.
+    pub fn with_span_fields_grouped(mut self, enable: bool) -> Self {
+        self.events.with_span_fields_grouped = enable;
+        self
+    }
+    /// Emit a `span_fields` field with the number of span fields written on
+    /// the line, after the span fields themselves. Useful to find out why
+    /// lines are large.
+    pub fn with_span_field_count(mut self, enable: bool) -> Self {
+        self.events.with_span_field_count = enable;
+        self
+    }
+    /// Also write the fields of the outermost span with a `root.` prefix,
+    /// e.g. `root.request_id=5`, right after the event fields, so that they
+    /// are easy to find on every line. A field is left out if the event has a
+    /// field with the same prefixed key.
+    pub fn with_root_span_fields(mut self, enable: bool) -> Self {
+        self.events.with_root_span_fields = enable;
+        self
+    }
+    /// Write at most `max_fields` fields of the event and its spans, followed
+    /// by `fields_truncated=true` if any were left out. The fields of the
+    /// event come first, then the ones of the spans from the root down.
+    pub fn with_max_fields(mut self, max_fields: usize) -> Self {
+        self.events.max_fields = Some(max_fields);
+        self
+    }
     pub fn with_location(mut self, enable: bool) -> Self {
         self.events.with_location = enable;
         self
     }
+    /// Emit a `caller` field with the file and line of the event, e.g.
+    /// `caller=src/main.rs:42`. Left out for events without file information.
+    pub fn with_caller(mut self, enable: bool) -> Self {
+        self.events.with_caller = enable;
+        self
+    }
     pub fn with_module_path(mut self, enable: bool) -> Self {
         self.events.with_module_path = enable;
         self
@@ -65,25 +1100,863 @@ impl Builder {
         self.events.with_timestamp = enable;
         self
     }
+    /// Sets the clock used for the `ts` field. Defaults to
+    /// [`SystemClock`](crate::SystemClock).
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.events.clock = clock;
+        self
+    }
+    /// Takes the `ts` from the given field of the event instead of the clock,
+    /// e.g. when replaying historical events. The field must contain an
+    /// RFC 3339 timestamp, and is then not written again. Events without the
+    /// field, or with a value that can't be parsed, use the clock.
+    pub fn with_timestamp_from_field(mut self, field: Option<String>) -> Self {
+        self.events.timestamp_from_field = field;
+        self
+    }
+    /// Emit the time of the event as nanoseconds since the Unix epoch with
+    /// the given key after the `ts`, e.g. `ts_nanos=1704207845123456789` for
+    /// exact sorting. Written even if [`Builder::with_timestamp`] is
+    /// disabled.
+    pub fn with_timestamp_nanos_field(mut self, key: Option<String>) -> Self {
+        self.events.timestamp_nanos_field = key;
+        self
+    }
+    /// Emit another timestamp of the event with `key` and `format`, e.g.
+    /// `ts_epoch=1704164645678` with [`TimestampFormat::UnixMillis`]. Can be
+    /// called multiple times; the timestamps are written in the order they
+    /// were added, after the `ts` and the
+    /// [`Builder::with_timestamp_nanos_field`], and are written even if
+    /// [`Builder::with_timestamp`] is disabled. All of them are taken from the
+    /// same reading of the clock.
+    pub fn with_additional_timestamp(
+        mut self,
+        key: impl Into<String>,
+        format: TimestampFormat,
+    ) -> Self {
+        self.events.additional_timestamps.push((key.into(), format));
+        self
+    }
+    /// Sets the order the builtin metadata fields are written in. Fields that
+    /// are not listed are written after the listed ones, in the default order
+    /// (`ts`, `seq`, `uptime_ms`, `event_id`, `level`, `kind`, `name`, `target`,
+    /// `crate`, `location`, `caller`, `module_path`, `span`, `span_path`,
+    /// `trace_id` and `span_id`, then the static fields).
+    ///
+    /// This only controls the order; whether a field is written at all is still
+    /// decided by its own setting.
+    pub fn with_field_order(mut self, order: impl IntoIterator<Item = BuiltinField>) -> Self {
+        let mut field_order = Vec::with_capacity(BuiltinField::DEFAULT_ORDER.len());
+        for field in order.into_iter().chain(BuiltinField::DEFAULT_ORDER) {
+            if !field_order.contains(&field) {
+                field_order.push(field);
+            }
+        }
+        self.events.field_order = field_order;
+        self
+    }
+    /// Sets whether the builtin metadata fields are written before the fields
+    /// of the event, or after the fields of the event and its spans. Defaults
+    /// to [`MetadataPosition::Prefix`].
+    pub fn with_metadata_position(mut self, position: MetadataPosition) -> Self {
+        self.events.metadata_position = position;
+        self
+    }
+    /// Emit a `seq` field with a counter that is incremented for every event,
+    /// starting at 0. This makes it possible to detect dropped lines.
+    ///
+    /// Values are unique and increasing per formatter, and shared between
+    /// clones of it, but lines from different threads may be written out of
+    /// order and an event that fails to format still consumes a number, so
+    /// the sequence is not guaranteed to be gap-free.
+    pub fn with_sequence_numbers(mut self, enable: bool) -> Self {
+        self.events.with_sequence_numbers = enable;
+        self
+    }
+
+    /// Emit an `uptime_ms` field with the number of milliseconds since the
+    /// formatter was created. Unlike `ts` this is monotonic, so it isn't
+    /// affected by the wall clock being adjusted.
+    pub fn with_uptime(mut self, enable: bool) -> Self {
+        self.events.with_uptime = enable;
+        self
+    }
+
+    /// Emit an `event_id` field with a random UUID (v4) for every event, so a
+    /// specific log line can be referenced from other systems.
+    #[cfg(feature = "event_id")]
+    pub fn with_event_id(mut self, enable: bool) -> Self {
+        self.events.with_event_id = enable;
+        self
+    }
+    /// Emit `trace_id` and `span_id` fields with the OpenTelemetry ids of the
+    /// span of the event, in hex, when the layer from `tracing-opentelemetry`
+    /// is part of the subscriber. Left out for events outside of a span, or
+    /// when the span has no valid OpenTelemetry context.
+    #[cfg(feature = "otel")]
+    pub fn with_otel_ids(mut self, enable: bool) -> Self {
+        self.events.with_otel_ids = enable;
+        self
+    }
+    /// Re-emit debug values that look like a [`std::time::Duration`] (`1.5s`,
+    /// `200ms`, `3µs`, ...) as a number with the unit appended to the key,
+    /// e.g. `elapsed_ms=1500`.
+    ///
+    /// tracing does not tell the formatter the type of a field, so this is
+    /// based on what the debug output looks like and applies to any field
+    /// recorded with `?` whose output matches.
+    pub fn with_duration_suffix_parsing(mut self, enable: bool) -> Self {
+        self.events.with_duration_suffix_parsing = enable;
+        self
+    }
+    /// The unit used by [`Builder::with_duration_suffix_parsing`]. Defaults to
+    /// milliseconds.
+    pub fn with_duration_unit(mut self, unit: DurationUnit) -> Self {
+        self.events.duration_unit = unit;
+        self
+    }
+    /// Sets which span lifecycle events are written, e.g. [`FmtSpan::CLOSE`]
+    /// for an event with the busy and idle time of each span when it closes.
+    /// Defaults to [`FmtSpan::NONE`].
+    ///
+    /// The events have the metadata and fields of the span, a `message` of
+    /// `new`, `enter`, `exit` or `close`, and the same value in a
+    /// `span_lifecycle` field, which other events don't have, to filter them
+    /// out. The field is written after `kind`, see [`Builder::with_field_order`].
+    pub fn with_span_events(mut self, span_events: FmtSpan) -> Self {
+        self.span_events = span_events;
+        self
+    }
+    /// Sets how the `time.busy` and `time.idle` fields of span close events
+    /// (see [`FmtSpan::CLOSE`]) are written.
+    /// By default they are left as tracing-subscriber formats them.
+    ///
+    /// tracing-subscriber rounds these to three significant digits before the
+    /// formatter sees them, so the numeric formats are not more precise.
+    pub fn with_span_duration_format(mut self, format: DurationFormat) -> Self {
+        self.events.span_duration_format = Some(format);
+        self
+    }
+    /// Sets what to do when an event records the same field name more than
+    /// once. Defaults to [`DuplicatePolicy::KeepAll`], which writes every
+    /// occurrence.
+    ///
+    /// Any other policy buffers the fields of the event before writing them.
+    pub fn with_duplicate_field_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.events.duplicate_policy = policy;
+        self
+    }
+    /// Write a `message` field with this value, after the fields of the event,
+    /// for events that do not record a message. Set it to an empty string to
+    /// only make sure that every line has a `message` key.
+    pub fn with_default_message(mut self, message: impl Into<String>) -> Self {
+        self.events.default_message = Some(message.into());
+        self
+    }
+    /// Leave out the `message` field if it is empty or only whitespace. If
+    /// [`Builder::with_default_message`] is also set, the default is written
+    /// instead.
+    pub fn with_skip_blank_message(mut self, enable: bool) -> Self {
+        self.events.with_skip_blank_message = enable;
+        self
+    }
+    /// Write the message first on the line without the `message=` key, e.g.
+    /// `"this happened" level=info foo=bar`. It is quoted if it needs to be,
+    /// like any other value. Has no effect on JSON output. Defaults to
+    /// `false`.
+    pub fn with_bare_message(mut self, enable: bool) -> Self {
+        self.events.with_bare_message = enable;
+        self
+    }
+    /// Record and write the fields of the event, including the message.
+    /// Disabling this writes only the metadata and span fields, e.g. for
+    /// heartbeat lines or to measure the cost of recording the fields.
+    /// Defaults to `true`.
+    pub fn with_fields(mut self, enable: bool) -> Self {
+        self.events.with_fields = enable;
+        self
+    }
+
+    /// Writes the message first and errors last, with all other fields in
+    /// between in the order they were recorded. Errors are fields recorded
+    /// as a `dyn Error`, or named `error` or `error.*`. Defaults to `false`.
+    ///
+    /// This applies to the event's own fields, span fields are still written
+    /// after them.
+    pub fn with_field_grouping(mut self, enable: bool) -> Self {
+        self.events.with_field_grouping = enable;
+        self
+    }
+    /// Sorts the fields of the event by key with `compare`, e.g. to write
+    /// some keys first in a house order and the rest alphabetically. The keys
+    /// are compared as written, after renames. The sort is stable, so fields
+    /// that compare equal keep the order they were recorded in, and with
+    /// [`Builder::with_field_grouping`] the fields are only sorted within
+    /// their group.
+    ///
+    /// Like the grouping, this applies to the event's own fields, span
+    /// fields are still written after them.
+    pub fn with_field_sort<F>(mut self, compare: F) -> Self
+    where
+        F: Fn(&str, &str) -> Ordering + Send + Sync + 'static,
+    {
+        self.events.field_sort = Some(Arc::new(compare));
+        self
+    }
+
+    /// Sets the characters written at the end of every line. Defaults to
+    /// [`LineEnding::Lf`].
+    pub fn with_line_ending(mut self, line_ending: LineEnding) -> Self {
+        self.events.line_ending = line_ending;
+        self
+    }
+
+    /// Controls whether a line ending is written after each event. Defaults
+    /// to `true`.
+    ///
+    /// Disabling it is only meant for custom writers that add their own
+    /// delimiters, e.g. when embedding logfmt lines in another format.
+    /// Writing to a plain stream without one will merge consecutive events
+    /// into a single line.
+    pub fn with_trailing_newline(mut self, enable: bool) -> Self {
+        self.events.with_trailing_newline = enable;
+        self
+    }
+    /// Append `suffix` to every line, after the last field and before the
+    /// line ending, e.g. a trailing comment for a specific parser.
+    ///
+    /// The suffix is written verbatim, without any quoting or escaping, so
+    /// it is up to the caller to keep the line valid for its consumer, e.g.
+    /// to start it with a space and to not include a newline.
+    pub fn with_line_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.events.line_suffix = Some(suffix.into());
+        self
+    }
+    /// Add the version of the application to every line, e.g. `version=1.2.3`,
+    /// written before the other static fields. Use
+    /// `env!("CARGO_PKG_VERSION")` for the version of the crate being built.
+    /// The value is quoted like any other if it contains spaces or other
+    /// characters that need quoting.
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.events.version = Some(version.into());
+        self
+    }
+    /// Add the git commit the application was built from to every line, e.g.
+    /// `git_sha=abc123`, written after the version. A build script can pass
+    /// the output of `git rev-parse --short HEAD` on to the crate with
+    /// `println!("cargo:rustc-env=GIT_SHA={}", sha)`:
+    ///
+    /// ```rust
+    /// let builder = tracing_logfmt::builder()
+    ///     .with_git_sha(option_env!("GIT_SHA").unwrap_or("unknown"));
+    /// ```
+    pub fn with_git_sha(mut self, git_sha: impl Into<String>) -> Self {
+        self.events.git_sha = Some(git_sha.into());
+        self
+    }
+    /// Add a field with a fixed value to every line, e.g. `service=checkout`.
+    /// Can be called multiple times; the fields are written in the order they
+    /// were added, after the other metadata fields unless moved with
+    /// [`BuiltinField::Static`] in [`Builder::with_field_order`].
+    pub fn with_static_field(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.events.static_fields.push((key.into(), value.into()));
+        self
+    }
+    /// Add the fields returned by `fields` to every line, after the static
+    /// fields, e.g. to add the id of the current request from a thread-local.
+    /// Can be called multiple times; the functions are called in the order
+    /// they were added.
+    ///
+    /// The function is called for every event, on the thread that logs it,
+    /// and allocates the returned fields, so keep it cheap. The fields are
+    /// quoted and escaped like any other, and fields with a key that can't
+    /// be written are left out.
+    pub fn with_dynamic_fields(
+        mut self,
+        fields: Arc<dyn Fn() -> Vec<(String, String)> + Send + Sync>,
+    ) -> Self {
+        self.events.dynamic_fields.push(fields);
+        self
+    }
+    /// Add every environment variable whose name starts with `prefix` as a
+    /// static field, with the prefix removed from the key, e.g.
+    /// `LOGFMT_FIELD_service=checkout` becomes `service=checkout`. Calling it
+    /// again replaces the prefix.
+    ///
+    /// The environment is read once, when the formatters are built, e.g. by
+    /// [`Builder::layer`]. Variables are added sorted by name after the fields
+    /// of [`Builder::with_static_field`]. Ones that are not valid unicode, or
+    /// whose key is empty or can't be written, are skipped.
+    pub fn with_static_fields_from_env(mut self, prefix: &str) -> Self {
+        self.static_fields_env_prefix = Some(prefix.to_owned());
+        self
+    }
+    /// Write the field `field_name` of the closest span that recorded it as
+    /// `output_key`, before the fields of the event, e.g. a `component` that
+    /// is only recorded on the root span. Lines in spans without the field
+    /// leave it out.
+    pub fn with_promoted_span_field(
+        mut self,
+        field_name: impl Into<String>,
+        output_key: impl Into<String>,
+    ) -> Self {
+        self.events
+            .promoted_span_fields
+            .push((field_name.into(), output_key.into()));
+        self
+    }
+    /// Write the recorded field `from` with the key `to` instead, e.g.
+    /// `message` as `msg`. Applies to both event and span fields.
+    pub fn with_field_rename(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.events
+            .field_options
+            .renames
+            .insert(from.into(), to.into());
+        self
+    }
+    /// Leave out recorded fields with these keys. Applies to both event and
+    /// span fields.
+    pub fn with_field_denylist(
+        mut self,
+        keys: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.events
+            .field_options
+            .denylist
+            .extend(keys.into_iter().map(Into::into));
+        self
+    }
+    /// Emit a `fields_dropped` field with the number of event fields left out
+    /// by [`Builder::with_field_denylist`] or [`Builder::with_value_transform`],
+    /// after the span fields. Left out if no fields were dropped.
+    pub fn with_filtered_count(mut self, enable: bool) -> Self {
+        self.events.with_filtered_count = enable;
+        self
+    }
+    /// Truncate the values of recorded fields to at most this many characters.
+    /// Numbers and booleans are never truncated. Applies to both event and span
+    /// fields.
+    pub fn with_max_value_length(mut self, max_length: usize) -> Self {
+        self.events.field_options.max_value_length = Some(max_length);
+        self
+    }
+    /// Write a `logfmt_error=invalid_key` field at the end of lines where a
+    /// recorded field was left out because its key had no valid characters.
+    /// Applies to both event and span fields.
+    pub fn with_error_field(mut self, enable: bool) -> Self {
+        self.events.field_options.with_error_field = enable;
+        self
+    }
+    /// Write recorded fields whose key has no valid characters with this key
+    /// and a number instead, e.g. `field_1=value`, rather than leaving them
+    /// out. The numbers start at 1 for each event and span.
+    pub fn with_fallback_key(mut self, key: impl Into<String>) -> Self {
+        self.events.field_options.fallback_key = Some(key.into());
+        self
+    }
+    /// Quote numbers and booleans, e.g. `count="5"`, for consumers that expect
+    /// every number to be quoted. Applies to both event and span fields.
+    pub fn with_quote_numbers(mut self, enable: bool) -> Self {
+        self.events.field_options.quote_numbers = enable;
+        self
+    }
+    /// Leave out fields recorded with `?` whose debug output is exactly
+    /// `None`. String fields with the value `"None"` and the message are
+    /// still written.
+    /// Applies to both event and span fields.
+    pub fn with_skip_none_debug(mut self, enable: bool) -> Self {
+        self.events.field_options.skip_none_debug = enable;
+        self
+    }
+    /// Suffix the keys of fields recorded as a number, bool, string or bytes
+    /// with their type, e.g. `count:int=5` and `name:str=5`, so that they
+    /// can be parsed back into the same type. Fields recorded with `?` or `%`
+    /// and the message are written without a type. Applies to both event and
+    /// span fields.
+    pub fn with_type_hints(mut self, enable: bool) -> Self {
+        self.events.field_options.type_hints = enable;
+        self
+    }
+    /// Format fields recorded with `?` with `{:#?}` instead of `{:?}`.
+    /// Applies to both event and span fields.
+    ///
+    /// The pretty output spans several lines, so those values are always
+    /// quoted with the newlines escaped, or replaced if
+    /// [`Builder::with_strip_newlines`] is enabled, which keeps each event on
+    /// one line but makes it longer and harder to scan. Mostly useful when
+    /// debugging locally.
+    pub fn with_pretty_debug(mut self, enable: bool) -> Self {
+        self.events.field_options.pretty_debug = enable;
+        self
+    }
+    /// Trim leading and trailing ASCII whitespace from string and debug
+    /// values, e.g. `msg="hello "` is written as `msg=hello`. Whitespace
+    /// inside the value is kept. Applies to both event and span fields,
+    /// before [`Builder::with_value_transform`]. Defaults to `false`.
+    pub fn with_trim_values(mut self, enable: bool) -> Self {
+        self.events.field_options.trim_values = enable;
+        self
+    }
+    /// Sets how byte slice fields are written. Defaults to
+    /// [`BytesEncoding::Hex`]. Applies to both event and span fields.
+    pub fn with_bytes_encoding(mut self, encoding: BytesEncoding) -> Self {
+        self.events.field_options.bytes_encoding = encoding;
+        self
+    }
+    /// Write the named fields of structs recorded with `valuable` as separate
+    /// fields with dotted keys, e.g. `db.host=localhost db.port=5432` for a
+    /// `db` field, instead of as a single debug value. Applies to both event
+    /// and span fields. Defaults to `true`.
+    ///
+    /// Field names that contain dots, like `db.host`, are always written as
+    /// they are.
+    pub fn with_dotted_fields(mut self, enable: bool) -> Self {
+        self.events.field_options.dotted_fields = enable;
+        self
+    }
+    /// Replace or drop field values before they are written. The function
+    /// is called with the name and value of every event and span field, and
+    /// returns the value to write or `None` to leave the field out. Applies
+    /// after [`Builder::with_field_denylist`] and before
+    /// [`Builder::with_max_value_length`].
+    ///
+    /// The function is called for every field of every line, so keep it
+    /// cheap. Numbers and other non-string values are formatted to a string
+    /// to be passed to it, which they otherwise aren't, and a value that is
+    /// returned unchanged should be borrowed to avoid allocating.
+    pub fn with_value_transform<F>(mut self, transform: F) -> Self
+    where
+        F: for<'v> Fn(&str, &'v str) -> Option<Cow<'v, str>> + Send + Sync + 'static,
+    {
+        self.events.field_options.value_transform = Some(Arc::new(transform));
+        self
+    }
+    /// Enable or disable ANSI colors, regardless of the writer and the
+    /// environment. Same as [`Builder::with_ansi_mode`] with
+    /// [`AnsiMode::Always`] or [`AnsiMode::Never`].
     #[cfg(feature = "ansi_logs")]
-    pub fn with_ansi_color(mut self, enable: bool) -> Self {
-        self.events.with_ansi_color = enable;
+    pub fn with_ansi_color(self, enable: bool) -> Self {
+        self.with_ansi_mode(if enable {
+            AnsiMode::Always
+        } else {
+            AnsiMode::Never
+        })
+    }
+    /// Sets when ANSI colors are used. Defaults to [`AnsiMode::Auto`], which
+    /// uses colors if stdout (or the writer passed to [`Builder::writer`]) is
+    /// a terminal, unless overridden by the `NO_COLOR` or `CLICOLOR_FORCE`
+    /// environment variables.
+    ///
+    /// The environment is read once, when this is called or when the layer is
+    /// created with [`Builder::writer`].
+    #[cfg(feature = "ansi_logs")]
+    pub fn with_ansi_mode(mut self, mode: AnsiMode) -> Self {
+        self.events.with_ansi_color = mode.resolve(|| std::io::stdout().is_terminal());
+        self.ansi_mode = mode;
         self
     }
+    /// Sets the color the level is written in for each level. Only used when
+    /// ANSI colors are enabled. Defaults to red, yellow, green, blue and
+    /// purple, from error to trace.
+    #[cfg(feature = "ansi_logs")]
+    pub fn with_level_colors(mut self, colors: LevelColors) -> Self {
+        self.events.level_colors = colors;
+        self
+    }
+
+    /// Quote and escape keys that contain characters that need quoting, e.g.
+    /// `"my key"=value`, instead of the default of stripping those characters
+    /// (`mykey=value`). Applies to both event and span fields.
+    ///
+    /// Not all logfmt parsers accept quoted keys.
+    pub fn with_quoted_keys(mut self, enable: bool) -> Self {
+        self.events.serializer.quote_keys = enable;
+        self
+    }
+    /// Sets the format lines are written in. Defaults to
+    /// [`OutputFormat::Logfmt`].
+    ///
+    /// All other settings apply to both formats, except for the ones that
+    /// control quoting and escaping, which JSON always does the same way. ANSI
+    /// colors are never used for JSON.
+    pub fn with_output_format(mut self, format: OutputFormat) -> Self {
+        self.events.serializer.output_format = format;
+        self
+    }
+    /// Sets how characters are escaped inside quoted values and keys. Defaults
+    /// to [`EscapeMode::Debug`].
+    pub fn with_escape_mode(mut self, mode: EscapeMode) -> Self {
+        self.events.serializer.escape_mode = mode;
+        self
+    }
+    /// Converts every key to `case` before it is written, both the keys of
+    /// the built-in fields and those of event and span fields, e.g. with
+    /// [`KeyCase::Snake`] `requestId` is written as `request_id`. Keys that
+    /// are already in the case are written without allocating. Defaults to
+    /// [`KeyCase::AsIs`].
+    pub fn with_key_case(mut self, case: KeyCase) -> Self {
+        self.events.serializer.key_case = case;
+        self
+    }
+    /// Also quote values that contain any of these characters, e.g. `|`.
+    /// Values are always quoted if they contain spaces, control characters,
+    /// `=` or `"`. Applies to both event and span fields, and to keys, which
+    /// are quoted or stripped of the characters the same way as other
+    /// characters that need quoting, see [`Builder::with_quoted_keys`].
+    pub fn with_quote_chars(mut self, chars: impl IntoIterator<Item = char>) -> Self {
+        self.events.serializer.quote_chars.extend(chars);
+        self
+    }
+    /// Escape `=` inside quoted values as `\=`, e.g. `url="a\=b&c\=d"`, for
+    /// parsers that split entries on every `=` even within quotes. Applies
+    /// to both event and span fields.
+    pub fn with_escape_equals(mut self, enable: bool) -> Self {
+        self.events.serializer.escape_equals = enable;
+        self
+    }
+    /// Sets the character that keys and values are quoted with, e.g. `'` for
+    /// `msg='hello world'`. Defaults to `"`. The character is escaped with a
+    /// backslash inside quoted values, and `"` is then no longer escaped.
+    /// Applies to both event and span fields.
+    ///
+    /// Letters, digits, whitespace, `=` and `\` can't be used, see
+    /// [`Builder::try_build`].
+    pub fn with_quote_char(mut self, quote: char) -> Self {
+        self.events.serializer.quote_char = quote;
+        self
+    }
+    /// Replace newlines (`\n`, `\r` and `\r\n`) inside values with a
+    /// placeholder, set with [`Builder::with_newline_placeholder`], before
+    /// they are written. Applies to both event and span fields.
+    ///
+    /// Newlines are always escaped in quoted values, but some log shippers
+    /// still split records on the escaped form.
+    pub fn with_strip_newlines(mut self, enable: bool) -> Self {
+        self.events.serializer.strip_newlines = enable;
+        self
+    }
+    /// The replacement for newlines used by [`Builder::with_strip_newlines`].
+    /// Defaults to `␤`.
+    pub fn with_newline_placeholder(mut self, placeholder: impl Into<String>) -> Self {
+        self.events.serializer.newline_placeholder = placeholder.into();
+        self
+    }
+
+    /// Checks that all configured keys and the quote char can be written, and
+    /// returns the event and span formatters.
+    pub fn try_build(self) -> Result<(EventsFormatter, FieldsFormatter), BuilderError> {
+        let options = &self.events.serializer;
+        let quote = options.quote_char;
+        if quote.is_alphanumeric() || quote.is_whitespace() || matches!(quote, '=' | '\\') {
+            return Err(BuilderError::InvalidQuoteChar(quote));
+        }
+
+        let keys = self
+            .events
+            .field_options
+            .renames
+            .values()
+            .map(|key| ("with_field_rename", key))
+            .chain([("with_level_key", &self.events.level_key)])
+            .chain(
+                self.events
+                    .verbosity_field
+                    .iter()
+                    .map(|key| ("with_verbosity_field", key)),
+            )
+            .chain(
+                self.events
+                    .timestamp_nanos_field
+                    .iter()
+                    .map(|key| ("with_timestamp_nanos_field", key)),
+            )
+            .chain(
+                self.events
+                    .additional_timestamps
+                    .iter()
+                    .map(|(key, _)| ("with_additional_timestamp", key)),
+            )
+            .chain(
+                self.events
+                    .extra_level_fields
+                    .iter()
+                    .map(|(key, _)| ("with_extra_level_field", key)),
+            )
+            .chain(
+                self.events
+                    .static_fields
+                    .iter()
+                    .map(|(key, _)| ("with_static_field", key)),
+            )
+            .chain(
+                self.events
+                    .promoted_span_fields
+                    .iter()
+                    .map(|(_, key)| ("with_promoted_span_field", key)),
+            )
+            .chain(
+                self.events
+                    .field_options
+                    .fallback_key
+                    .iter()
+                    .map(|key| ("with_fallback_key", key)),
+            );
+
+        for (setting, key) in keys {
+            if !options.is_valid_key(key) {
+                return Err(BuilderError::InvalidKey {
+                    setting,
+                    key: key.clone(),
+                });
+            }
+        }
+
+        Ok(self.into_formatters())
+    }
+
+    /// Like [`Builder::try_build`], but panics if a setting is invalid.
+    pub fn build(self) -> (EventsFormatter, FieldsFormatter) {
+        match self.try_build() {
+            Ok(formatters) => formatters,
+            Err(error) => panic!("invalid logfmt formatter setting: {}", error),
+        }
+    }
 
+    /// Creates a layer with the formatters.
+    ///
+    /// # Panics
+    ///
+    /// If a setting is invalid, see [`Builder::try_build`].
     pub fn layer<S>(self) -> Layer<S, FieldsFormatter, EventsFormatter>
     where
         S: Subscriber + for<'a> LookupSpan<'a>,
     {
+        let span_events = self.span_events.clone();
+        let (events, fields) = self.build();
         tracing_subscriber::fmt::layer()
-            .event_format(self.events)
-            .fmt_fields(self.fields)
+            .with_span_events(span_events)
+            .event_format(events)
+            .fmt_fields(fields)
     }
 
+    /// Creates a layer that writes to `make_writer`.
+    ///
+    /// With the `ansi_logs` feature and [`AnsiMode::Auto`], colors are enabled
+    /// if the writer is a terminal and disabled otherwise, unless overridden by
+    /// the environment. The check is done once, on a writer created from
+    /// `make_writer`.
+    ///
+    /// Example:
+    /// ```rust
+    /// use tracing_subscriber::Registry;
+    /// use tracing_subscriber::layer::SubscriberExt;
+    ///
+    /// let subscriber = Registry::default()
+    ///     .with(tracing_logfmt::builder().writer(std::io::stderr));
+    /// ```
+    #[cfg_attr(not(feature = "ansi_logs"), allow(unused_mut))]
+    pub fn writer<S, W>(mut self, make_writer: W) -> Layer<S, FieldsFormatter, EventsFormatter, W>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        W: for<'w> MakeWriter<'w> + 'static,
+        for<'w> <W as MakeWriter<'w>>::Writer: IsTerminal,
+    {
+        #[cfg(feature = "ansi_logs")]
+        if self.ansi_mode == AnsiMode::Auto {
+            self.events.with_ansi_color =
+                AnsiMode::Auto.resolve(|| make_writer.make_writer().is_terminal());
+        }
+
+        self.layer().with_writer(make_writer)
+    }
+
+    /// Creates a layer that writes to a file in `directory` that is rotated
+    /// daily, e.g. `app.log.2024-01-02` for the prefix `app.log`, from a
+    /// background thread so that logging doesn't block on the file.
+    ///
+    /// Lines that are still buffered are written when the returned guard is
+    /// dropped, so keep it alive until the end of `main`, and don't bind it to
+    /// `_`, which drops it right away.
+    ///
+    /// With the `ansi_logs` feature and [`AnsiMode::Auto`], colors are
+    /// disabled unless forced by the environment.
+    ///
+    /// Example:
+    /// ```rust
+    /// use tracing_subscriber::Registry;
+    /// use tracing_subscriber::layer::SubscriberExt;
+    ///
+    /// let (layer, _guard) = tracing_logfmt::builder().rotating_file("logs", "app.log");
+    /// let subscriber = Registry::default().with(layer);
+    /// # std::fs::remove_dir_all("logs").unwrap();
+    /// ```
+    #[cfg(feature = "tracing-appender")]
+    #[cfg_attr(not(feature = "ansi_logs"), allow(unused_mut))]
+    pub fn rotating_file<S>(
+        mut self,
+        directory: impl AsRef<std::path::Path>,
+        prefix: impl AsRef<std::path::Path>,
+    ) -> (
+        Layer<S, FieldsFormatter, EventsFormatter, tracing_appender::non_blocking::NonBlocking>,
+        tracing_appender::non_blocking::WorkerGuard,
+    )
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        #[cfg(feature = "ansi_logs")]
+        if self.ansi_mode == AnsiMode::Auto {
+            self.events.with_ansi_color = AnsiMode::Auto.resolve(|| false);
+        }
+
+        let appender = tracing_appender::rolling::daily(directory, prefix);
+        let (non_blocking, guard) = tracing_appender::non_blocking(appender);
+        (self.layer().with_writer(non_blocking), guard)
+    }
+
+    /// Creates a layer that writes every line to both writers, with ANSI
+    /// colors to `color_writer` and without them to `plain_writer`, e.g. to
+    /// log to the terminal and to a file at the same time.
+    ///
+    /// Both outputs get every field and their own sequence numbers.
+    ///
+    /// Example:
+    /// ```rust
+    /// use tracing_subscriber::Registry;
+    /// use tracing_subscriber::layer::SubscriberExt;
+    ///
+    /// let file = std::fs::File::create("app.log").unwrap();
+    /// let subscriber = Registry::default()
+    ///     .with(tracing_logfmt::builder().tee(std::io::stderr, std::sync::Mutex::new(file)));
+    /// # std::fs::remove_file("app.log").unwrap();
+    /// ```
+    #[cfg(feature = "ansi_logs")]
+    pub fn tee<S, A, B>(self, color_writer: A, plain_writer: B) -> impl tracing_subscriber::Layer<S>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        A: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+        B: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+    {
+        let color = self
+            .clone()
+            .with_ansi_color(true)
+            .layer()
+            .with_writer(color_writer);
+        let mut plain = self.with_ansi_color(false);
+        // each output numbers its own lines
+        plain.events.sequence = Arc::new(std::sync::atomic::AtomicU64::new(0));
+        let plain = plain.layer().with_writer(plain_writer);
+        color.and_then(plain)
+    }
+
+    /// Runs `f` with a subscriber that formats all its events and spans into
+    /// memory, and returns the formatted lines, e.g. to write assertions on
+    /// the output in tests. Events of all levels are captured.
+    ///
+    /// With the `ansi_logs` feature and [`AnsiMode::Auto`], colors are
+    /// disabled.
+    ///
+    /// # Panics
+    ///
+    /// If a setting is invalid, see [`Builder::try_build`].
+    ///
+    /// Example:
+    /// ```rust
+    /// let output = tracing_logfmt::builder()
+    ///     .with_timestamp(false)
+    ///     .with_target(false)
+    ///     .capture(|| tracing::debug!(answer = 42, "hello"));
+    ///
+    /// assert_eq!(output, b"level=debug message=hello answer=42\n");
+    /// ```
+    #[cfg_attr(not(feature = "ansi_logs"), allow(unused_mut))]
+    pub fn capture(mut self, f: impl FnOnce()) -> Vec<u8> {
+        use tracing_subscriber::layer::SubscriberExt as _;
+
+        #[cfg(feature = "ansi_logs")]
+        if self.ansi_mode == AnsiMode::Auto {
+            self.events.with_ansi_color = AnsiMode::Auto.resolve(|| false);
+        }
+
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let make_writer = {
+            let buffer = buffer.clone();
+            move || CaptureWriter(buffer.clone())
+        };
+        let subscriber =
+            tracing_subscriber::Registry::default().with(self.layer().with_writer(make_writer));
+        tracing::subscriber::with_default(subscriber, f);
+
+        let mut buffer = buffer.lock().unwrap_or_else(|error| error.into_inner());
+        std::mem::take(&mut *buffer)
+    }
+
+    /// Creates a subscriber builder with the formatters.
+    ///
+    /// # Panics
+    ///
+    /// If a setting is invalid, see [`Builder::try_build`].
     pub fn subscriber_builder(self) -> SubscriberBuilder<FieldsFormatter, EventsFormatter> {
+        let span_events = self.span_events.clone();
+        let (events, fields) = self.build();
         tracing_subscriber::fmt::Subscriber::builder()
-            .event_format(self.events)
-            .fmt_fields(self.fields)
+            .with_span_events(span_events)
+            .event_format(events)
+            .fmt_fields(fields)
+    }
+
+    /// Settings that apply to both event and span fields are only stored on
+    /// the events formatter while building, and copied over here.
+    fn into_formatters(self) -> (EventsFormatter, FieldsFormatter) {
+        self.into_formatters_with_vars(std::env::vars_os)
+    }
+
+    /// Like [`Builder::into_formatters`], with the static fields of
+    /// [`Builder::with_static_fields_from_env`] read from `vars`.
+    pub(crate) fn into_formatters_with_vars<I>(
+        self,
+        vars: impl FnOnce() -> I,
+    ) -> (EventsFormatter, FieldsFormatter)
+    where
+        I: IntoIterator<Item = (OsString, OsString)>,
+    {
+        let Self {
+            mut events,
+            mut fields,
+            static_fields_env_prefix,
+            ..
+        } = self;
+        if let Some(prefix) = static_fields_env_prefix {
+            let mut env_fields: Vec<_> = vars()
+                .into_iter()
+                .filter_map(|(name, value)| {
+                    let key = name.to_str()?.strip_prefix(&prefix)?.to_owned();
+                    Some((key, value.into_string().ok()?))
+                })
+                .filter(|(key, _)| events.serializer.is_valid_key(key))
+                .collect();
+            env_fields.sort();
+            events.static_fields.extend(env_fields);
+        }
+        // every build gives a separate layer, even from clones of a builder
+        events.instance_id = crate::formatter::next_instance_id();
+        fields.field_options = events.field_options.clone();
+        fields.serializer = events.serializer.clone();
+        (events, fields)
+    }
+}
+
+/// Appends everything written to a shared buffer, see [`Builder::capture`].
+struct CaptureWriter(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for CaptureWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut buffer = self.0.lock().unwrap_or_else(|error| error.into_inner());
+        buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
     }
 }
 
@@ -15,8 +15,24 @@
 
 #![deny(unreachable_pub)]
 
+mod clock;
+mod config;
+mod duration;
 mod formatter;
+pub mod prelude;
 mod serializer;
+mod writer;
 
-pub use crate::formatter::builder::{builder, Builder};
-pub use crate::formatter::{layer, EventsFormatter, FieldsFormatter};
+pub use crate::clock::{Clock, SystemClock};
+pub use crate::config::{LogfmtConfig, SpanEvent};
+pub use crate::duration::{DurationFormat, DurationUnit};
+pub use crate::formatter::builder::{builder, Builder, BuilderError};
+pub use crate::formatter::{
+    layer, BuiltinField, BytesEncoding, DuplicatePolicy, EventsFormatter, FieldsFormatter,
+    LineEnding, MetadataPosition, SpanRef, TimestampFormat,
+};
+#[cfg(feature = "ansi_logs")]
+pub use crate::formatter::{AnsiColor, AnsiMode, LevelColors};
+pub use crate::serializer::{EscapeMode, KeyCase, OutputFormat};
+pub use crate::writer::{DedupTimestampWriter, LogfmtWriter};
+pub use tracing_subscriber::fmt::format::FmtSpan;
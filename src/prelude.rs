@@ -0,0 +1,15 @@
+//! Re-exports the functions and traits needed to set up the formatter.
+//!
+//! Example:
+//! ```rust
+//! use tracing_logfmt::prelude::*;
+//!
+//! tracing_subscriber::registry()
+//!     .with(tracing_logfmt::builder().with_target(false).layer())
+//!     .init();
+//! ```
+
+pub use crate::{builder, layer, Builder};
+
+pub use tracing_subscriber::layer::SubscriberExt as _;
+pub use tracing_subscriber::util::SubscriberInitExt as _;
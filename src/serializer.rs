@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::fmt;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -12,28 +13,250 @@ impl From<std::fmt::Error> for SerializerError {
     }
 }
 
+/// How characters are escaped inside quoted values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum EscapeMode {
+    /// Escape like Rust's `str::escape_debug`, e.g. `\x10` becomes `\u{10}`.
+    #[default]
+    Debug,
+    /// Only escape `"`, `\`, newlines, carriage returns and tabs, and leave all
+    /// other characters as they are. This matches the escaping of Go logfmt
+    /// libraries.
+    Minimal,
+}
+
+/// The case keys are converted to before they are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum KeyCase {
+    /// Write keys as they are.
+    #[default]
+    AsIs,
+    /// `requestId` and `request-id` become `request_id`.
+    Snake,
+    /// `requestId` and `request_id` become `request-id`.
+    Kebab,
+}
+
+impl KeyCase {
+    /// Converts `key`, borrowing it if it is already in this case. Words
+    /// start at an uppercase letter after a lowercase letter or digit, or
+    /// at the last of a run of uppercase letters followed by a lowercase
+    /// one, so `HTTPServer` becomes `http_server` and `userID` becomes
+    /// `user_id`. Other characters, like the `.` in `time.busy`, are kept.
+    pub(crate) fn convert(self, key: &str) -> Cow<'_, str> {
+        let (separator, other) = match self {
+            KeyCase::AsIs => return Cow::Borrowed(key),
+            KeyCase::Snake => ('_', '-'),
+            KeyCase::Kebab => ('-', '_'),
+        };
+        if !key.chars().any(|ch| ch.is_uppercase() || ch == other) {
+            return Cow::Borrowed(key);
+        }
+
+        let mut converted = String::with_capacity(key.len() + 4);
+        let mut prev: Option<char> = None;
+        let mut chars = key.chars().peekable();
+        while let Some(ch) = chars.next() {
+            if ch == other {
+                converted.push(separator);
+            } else if ch.is_uppercase() {
+                let word_start = match prev {
+                    Some(prev) if prev.is_lowercase() || prev.is_numeric() => true,
+                    Some(prev) if prev.is_uppercase() => {
+                        chars.peek().is_some_and(|next| next.is_lowercase())
+                    }
+                    _ => false,
+                };
+                if word_start {
+                    converted.push(separator);
+                }
+                converted.extend(ch.to_lowercase());
+            } else {
+                converted.push(ch);
+            }
+            prev = Some(ch);
+        }
+
+        Cow::Owned(converted)
+    }
+}
+
+/// The format lines are written in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum OutputFormat {
+    /// `key=value` pairs separated by spaces.
+    #[default]
+    Logfmt,
+    /// A JSON object per line, with the same keys and values as logfmt.
+    /// Numbers and booleans are written as JSON numbers and booleans,
+    /// everything else as strings.
+    #[cfg(feature = "json")]
+    Json,
+}
+
+/// Settings that control how keys and values are escaped and quoted.
+///
+/// These are shared by the events and fields formatters so that event fields
+/// and span fields are written the same way.
+#[derive(Clone, Debug)]
+pub(crate) struct SerializerOptions {
+    /// Quote keys that contain characters that need quoting, instead of
+    /// stripping those characters.
+    pub(crate) quote_keys: bool,
+    pub(crate) escape_mode: EscapeMode,
+    /// Replace newlines in values with `newline_placeholder`.
+    pub(crate) strip_newlines: bool,
+    pub(crate) newline_placeholder: String,
+    /// Characters that make a value quoted, in addition to those that always
+    /// need quoting.
+    pub(crate) quote_chars: Vec<char>,
+    /// Escape `=` inside quoted values as `\=`.
+    pub(crate) escape_equals: bool,
+    /// The character that quoted keys and values are wrapped in.
+    pub(crate) quote_char: char,
+    pub(crate) key_case: KeyCase,
+    /// Only the logfmt output uses the settings above, JSON is always quoted
+    /// and escaped the same way.
+    pub(crate) output_format: OutputFormat,
+}
+
+impl SerializerOptions {
+    /// Whether a key can be written. Without quoted keys, a key must have at
+    /// least one character that does not need quoting.
+    pub(crate) fn is_valid_key(&self, key: &str) -> bool {
+        !key.is_empty()
+            && (self.quote_keys || self.is_json() || !key.chars().all(|ch| self.needs_quote(ch)))
+    }
+
+    /// Whether a logfmt key or value that contains `ch` has to be quoted,
+    /// with the configured quote char and extra quote chars.
+    pub(crate) fn needs_quote(&self, ch: char) -> bool {
+        need_quote(ch) || ch == self.quote_char || self.quote_chars.contains(&ch)
+    }
+
+    pub(crate) fn is_json(&self) -> bool {
+        // without the `json` feature this is always `false`
+        self.output_format != OutputFormat::Logfmt
+    }
+}
+
+impl Default for SerializerOptions {
+    fn default() -> Self {
+        Self {
+            quote_keys: false,
+            escape_mode: EscapeMode::default(),
+            strip_newlines: false,
+            newline_placeholder: "␤".to_owned(),
+            quote_chars: Vec::new(),
+            escape_equals: false,
+            quote_char: '"',
+            key_case: KeyCase::default(),
+            output_format: OutputFormat::default(),
+        }
+    }
+}
+
 /// Serializes key/value pairs into logfmt format.
-pub(crate) struct Serializer<W> {
+pub(crate) struct Serializer<'o, W> {
     pub(crate) writer: W,
+    options: &'o SerializerOptions,
     writing_first_entry: bool,
     #[cfg(feature = "ansi_logs")]
     with_ansi_color: bool,
 }
 
-impl<W> Serializer<W>
+impl<'o, W> Serializer<'o, W>
 where
     W: fmt::Write,
 {
     #[inline]
-    pub(crate) fn new(writer: W, #[cfg(feature = "ansi_logs")] with_ansi_color: bool) -> Self {
+    pub(crate) fn new(
+        writer: W,
+        options: &'o SerializerOptions,
+        #[cfg(feature = "ansi_logs")] with_ansi_color: bool,
+    ) -> Self {
         Serializer {
             writer,
+            options,
             writing_first_entry: true,
             #[cfg(feature = "ansi_logs")]
-            with_ansi_color,
+            with_ansi_color: with_ansi_color && !options.is_json(),
+        }
+    }
+
+    /// `formatted` without the separators in front of its first entry.
+    pub(crate) fn trim_leading_separator<'s>(&self, formatted: &'s str) -> &'s str {
+        formatted.trim_start_matches(self.entry_separator())
+    }
+
+    /// Writes what comes before the first entry of a line, `{` for JSON.
+    pub(crate) fn begin_line(&mut self) -> Result<(), SerializerError> {
+        if self.options.is_json() {
+            self.writer.write_char('{')?;
+        }
+        Ok(())
+    }
+
+    /// Writes what comes after the last entry of a line, `}` for JSON.
+    pub(crate) fn end_line(&mut self) -> Result<(), SerializerError> {
+        if self.options.is_json() {
+            self.writer.write_char('}')?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_json(&self) -> bool {
+        self.options.is_json()
+    }
+
+    fn entry_separator(&self) -> char {
+        if self.options.is_json() {
+            ','
+        } else {
+            ' '
         }
     }
 
+    fn key_value_separator(&self) -> char {
+        if self.options.is_json() {
+            ':'
+        } else {
+            '='
+        }
+    }
+
+    /// Writes a value without a key at the start of the line, quoted as
+    /// needed.
+    pub(crate) fn serialize_bare_value(&mut self, value: &str) -> Result<(), SerializerError> {
+        self.serialize_value(value)?;
+        self.continue_line();
+        Ok(())
+    }
+
+    /// Continue a line that already has entries, so that the next entry is
+    /// preceded by a separator.
+    pub(crate) fn continue_line(&mut self) {
+        self.writing_first_entry = false;
+    }
+
     #[cfg(not(feature = "ansi_logs"))]
     pub(crate) fn serialize_entry(
         &mut self,
@@ -49,13 +272,28 @@ where
         key: &str,
         value: &str,
     ) -> Result<(), SerializerError> {
-        if let "level" = key {
+        if key == "level" && !self.options.is_json() {
             self.serialize_entry_with(key, value, |this, value| this.serialize_level(value))
         } else {
             self.serialize_entry_with(key, value, |this, value| this.serialize_value(value))
         }
     }
 
+    /// Writes the level, which may already have been colored, with `key`.
+    pub(crate) fn serialize_level_entry(
+        &mut self,
+        key: &str,
+        value: &str,
+    ) -> Result<(), SerializerError> {
+        #[cfg(feature = "ansi_logs")]
+        if !self.options.is_json() {
+            return self
+                .serialize_entry_with(key, value, |this, value| this.serialize_level(value));
+        }
+
+        self.serialize_entry_with(key, value, |this, value| this.serialize_value(value))
+    }
+
     pub(crate) fn serialize_entry_no_quote(
         &mut self,
         key: &str,
@@ -66,6 +304,78 @@ where
         })
     }
 
+    /// Writes entries that have already been formatted, e.g. the fields of a
+    /// span, as if they had been written with this serializer.
+    pub(crate) fn serialize_formatted_entries(
+        &mut self,
+        entries: impl fmt::Display,
+    ) -> Result<(), SerializerError> {
+        if !self.writing_first_entry {
+            self.writer.write_char(self.entry_separator())?;
+        }
+        self.writing_first_entry = false;
+        write!(self.writer, "{}", entries)?;
+
+        Ok(())
+    }
+
+    /// Writes a single entry whose key and value have already been formatted,
+    /// e.g. one taken from [`Serializer::formatted_entries`].
+    pub(crate) fn serialize_formatted_pair(
+        &mut self,
+        key: impl fmt::Display,
+        value: &str,
+    ) -> Result<(), SerializerError> {
+        let separator = self.key_value_separator();
+        self.serialize_formatted_entries(format_args!("{}{}{}", key, separator, value))
+    }
+
+    /// Iterates over entries written with the same options as this
+    /// serializer, e.g. the fields of a span.
+    pub(crate) fn formatted_entries<'s>(&self, formatted: &'s str) -> FormattedEntries<'s> {
+        FormattedEntries {
+            entry_separator: self.entry_separator(),
+            key_value_separator: self.key_value_separator(),
+            ..FormattedEntries::new(formatted, self.quote_char())
+        }
+    }
+
+    /// Writes the key and the separator after it, for values that are
+    /// written directly to the writer.
+    pub(crate) fn serialize_key_and_separator(&mut self, key: &str) -> Result<(), SerializerError> {
+        self.serialize_key(key)?;
+        self.writer.write_char(self.key_value_separator())?;
+        Ok(())
+    }
+
+    /// Like `serialize_entry`, but the value has already been serialized, e.g.
+    /// when it is taken from the formatted fields of a span.
+    pub(crate) fn serialize_entry_formatted(
+        &mut self,
+        key: &str,
+        value: &str,
+    ) -> Result<(), SerializerError> {
+        self.serialize_entry_with(key, value, |this, value| {
+            this.writer.write_str(value)?;
+            Ok(())
+        })
+    }
+
+    /// Like `serialize_entry`, but the value is always quoted, even if it does
+    /// not contain any characters that need quoting.
+    pub(crate) fn serialize_entry_quoted(
+        &mut self,
+        key: &str,
+        value: &str,
+    ) -> Result<(), SerializerError> {
+        self.serialize_entry_with(key, value, |this, value| {
+            this.writer.write_char(this.quote_char())?;
+            this.write_escaped(value)?;
+            this.writer.write_char(this.quote_char())?;
+            Ok(())
+        })
+    }
+
     fn serialize_entry_with<F, T>(
         &mut self,
         key: &str,
@@ -75,24 +385,52 @@ where
     where
         F: FnOnce(&mut Self, T) -> Result<(), SerializerError>,
     {
-        self.serialize_key(key)?;
-        self.writer.write_char('=')?;
+        self.serialize_key_and_separator(key)?;
         serialize_value(self, value)?;
 
         Ok(())
     }
+    /// Whether a value that contains `ch` has to be quoted.
+    pub(crate) fn value_needs_quote(&self, ch: char) -> bool {
+        self.options.is_json() || self.options.needs_quote(ch)
+    }
+
+    pub(crate) fn quote_char(&self) -> char {
+        if self.options.is_json() {
+            '"'
+        } else {
+            self.options.quote_char
+        }
+    }
+
+    /// Whether `serialize_key` accepts the key.
+    pub(crate) fn is_valid_key(&self, key: &str) -> bool {
+        self.options.is_valid_key(key)
+    }
+
     pub(crate) fn serialize_key(&mut self, key: &str) -> Result<(), SerializerError> {
+        // checked before anything is written, so that the entry can be skipped
+        if !self.is_valid_key(key) {
+            return Err(SerializerError::InvalidKey);
+        }
+
+        let options = self.options;
+        let key = options.key_case.convert(key);
+        let key = key.as_ref();
+        let quote = options.is_json()
+            || options.quote_keys && key.chars().any(|ch| options.needs_quote(ch));
+
         if !self.writing_first_entry {
-            self.writer.write_char(' ')?;
+            self.writer.write_char(self.entry_separator())?;
         }
         self.writing_first_entry = false;
 
-        let mut chars = key.chars().filter(|&ch| !need_quote(ch)).peekable();
-
-        if chars.peek().is_none() {
-            return Err(SerializerError::InvalidKey);
+        if quote {
+            return self.serialize_quoted_key(key);
         }
 
+        let chars = key.chars().filter(|&ch| !options.needs_quote(ch));
+
         #[cfg(not(feature = "ansi_logs"))]
         {
             for c in chars {
@@ -122,19 +460,193 @@ where
         Ok(())
     }
 
+    fn serialize_quoted_key(&mut self, key: &str) -> Result<(), SerializerError> {
+        #[cfg(feature = "ansi_logs")]
+        {
+            if self.with_ansi_color {
+                let mut quoted =
+                    Serializer::new(String::with_capacity(key.len() + 2), self.options, false);
+                quoted.writer.push(self.quote_char());
+                quoted.write_escaped(key)?;
+                quoted.writer.push(self.quote_char());
+                let quoted = quoted.writer;
+                self.writer.write_str(
+                    &nu_ansi_term::Color::Rgb(109, 139, 140)
+                        .bold()
+                        .paint(quoted)
+                        .to_string(),
+                )?;
+                return Ok(());
+            }
+        }
+
+        let quote = self.quote_char();
+        self.writer.write_char(quote)?;
+        self.write_escaped(key)?;
+        self.writer.write_char(quote)?;
+        Ok(())
+    }
+
     pub(crate) fn serialize_value(&mut self, value: &str) -> Result<(), SerializerError> {
-        if value.chars().any(need_quote) {
-            self.writer.write_char('"')?;
-            write!(self.writer, "{}", value.escape_debug())?;
-            self.writer.write_char('"')?;
+        self.serialize_value_with(value, false)
+    }
+
+    /// Like [`Serializer::serialize_value`], but quoted even if the value
+    /// doesn't need it.
+    pub(crate) fn serialize_value_quoted(&mut self, value: &str) -> Result<(), SerializerError> {
+        self.serialize_value_with(value, true)
+    }
+
+    fn serialize_value_with(
+        &mut self,
+        value: &str,
+        always_quote: bool,
+    ) -> Result<(), SerializerError> {
+        if self.options.strip_newlines && value.contains(['\n', '\r']) {
+            let value = value
+                .replace("\r\n", "\n")
+                .replace(['\n', '\r'], &self.options.newline_placeholder);
+            return self.serialize_value_escaped(&value, always_quote);
+        }
+
+        self.serialize_value_escaped(value, always_quote)
+    }
+
+    fn serialize_value_escaped(
+        &mut self,
+        value: &str,
+        always_quote: bool,
+    ) -> Result<(), SerializerError> {
+        // the start of the value before the first char that needs quoting is
+        // only scanned once, and written as is if it needs no escaping either
+        let mut plain = true;
+        let mut quote_at = None;
+        for (index, ch) in value.char_indices() {
+            if self.value_needs_quote(ch) {
+                quote_at = Some(index);
+                break;
+            }
+            plain = plain && self.is_plain(ch);
+        }
+
+        let quote_at = match quote_at {
+            Some(index) => index,
+            None if always_quote || self.options.is_json() => value.len(),
+            None => {
+                self.writer.write_str(value)?;
+                return Ok(());
+            }
+        };
+
+        let (start, rest) = value.split_at(quote_at);
+        let quote = self.quote_char();
+        self.writer.write_char(quote)?;
+        if plain {
+            self.writer.write_str(start)?;
+            self.write_escaped(rest)?;
         } else {
-            self.writer.write_str(value)?;
+            self.write_escaped(value)?;
         }
+        self.writer.write_char(quote)?;
 
         Ok(())
     }
 
+    /// Whether `ch` is written the same inside quotes, when it doesn't need
+    /// quoting itself.
+    pub(crate) fn is_plain(&self, ch: char) -> bool {
+        match self.options.escape_mode {
+            EscapeMode::Debug => ch.is_ascii_graphic() && !matches!(ch, '\\' | '\'' | '"'),
+            EscapeMode::Minimal => ch != '\\',
+        }
+    }
+
+    fn write_escaped(&mut self, value: &str) -> Result<(), SerializerError> {
+        if self.options.is_json() {
+            return self.write_json_escaped(value);
+        }
+        if !self.options.escape_equals {
+            return self.write_escaped_with_mode(value);
+        }
+
+        for (index, part) in value.split('=').enumerate() {
+            if index > 0 {
+                self.writer.write_str(r"\=")?;
+            }
+            self.write_escaped_with_mode(part)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_escaped_with_mode(&mut self, value: &str) -> Result<(), SerializerError> {
+        let quote = self.options.quote_char;
+        match self.options.escape_mode {
+            EscapeMode::Debug if quote == '"' => write!(self.writer, "{}", value.escape_debug())?,
+            // `escape_debug` always escapes `"`, which is left as is inside
+            // other quotes, and doesn't know about the quote char
+            EscapeMode::Debug => {
+                let mut rest = value;
+                while let Some(index) = rest.find(['"', quote]) {
+                    write!(self.writer, "{}", rest[..index].escape_debug())?;
+                    let ch = rest[index..].chars().next().unwrap_or(quote);
+                    if ch == quote {
+                        self.writer.write_char('\\')?;
+                    }
+                    self.writer.write_char(ch)?;
+                    rest = &rest[index + ch.len_utf8()..];
+                }
+                write!(self.writer, "{}", rest.escape_debug())?;
+            }
+            EscapeMode::Minimal => {
+                for ch in value.chars() {
+                    match ch {
+                        ch if ch == quote => {
+                            self.writer.write_char('\\')?;
+                            self.writer.write_char(ch)?;
+                        }
+                        '\\' => self.writer.write_str(r"\\")?,
+                        '\n' => self.writer.write_str(r"\n")?,
+                        '\r' => self.writer.write_str(r"\r")?,
+                        '\t' => self.writer.write_str(r"\t")?,
+                        ch => self.writer.write_char(ch)?,
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_json_escaped(&mut self, value: &str) -> Result<(), SerializerError> {
+        for ch in value.chars() {
+            match ch {
+                '"' => self.writer.write_str(r#"\""#)?,
+                '\\' => self.writer.write_str(r"\\")?,
+                '\n' => self.writer.write_str(r"\n")?,
+                '\r' => self.writer.write_str(r"\r")?,
+                '\t' => self.writer.write_str(r"\t")?,
+                ch if ch < ' ' => write!(self.writer, "\\u{:04x}", u32::from(ch))?,
+                ch => self.writer.write_char(ch)?,
+            }
+        }
+        Ok(())
+    }
+
     fn serialize_value_no_quote(&mut self, value: impl fmt::Debug) -> Result<(), SerializerError> {
+        if self.options.is_json() {
+            // e.g. `NaN` or a debug value that isn't a number has to be a string
+            let value = format!("{:?}", value);
+            if is_json_literal(&value) {
+                self.writer.write_str(&value)?;
+            } else {
+                self.writer.write_char('"')?;
+                self.write_json_escaped(&value)?;
+                self.writer.write_char('"')?;
+            }
+            return Ok(());
+        }
+
         write!(self.writer, "{:?}", value)?;
         Ok(())
     }
@@ -146,20 +658,111 @@ where
     }
 }
 
+/// Iterates over the `key=value` entries of an already formatted line, e.g.
+/// the fields of a span, yielding the key and value as they were written,
+/// including any quotes.
+pub(crate) struct FormattedEntries<'s> {
+    rest: &'s str,
+    quote: char,
+    entry_separator: char,
+    key_value_separator: char,
+}
+
+impl<'s> FormattedEntries<'s> {
+    /// Iterates over logfmt entries, see [`Serializer::formatted_entries`]
+    /// for entries written in the output format of a serializer.
+    pub(crate) fn new(formatted: &'s str, quote: char) -> Self {
+        Self {
+            rest: formatted,
+            quote,
+            entry_separator: ' ',
+            key_value_separator: '=',
+        }
+    }
+
+    /// The entries that haven't been iterated over yet, as formatted.
+    pub(crate) fn rest(&self) -> &'s str {
+        self.rest
+    }
+}
+
+impl<'s> Iterator for FormattedEntries<'s> {
+    type Item = (&'s str, &'s str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.rest.trim_start_matches(self.entry_separator);
+        if rest.is_empty() {
+            self.rest = rest;
+            return None;
+        }
+
+        let key_end = token_end(rest, self.key_value_separator, self.quote);
+        let key = &rest[..key_end];
+        let rest = rest[key_end..]
+            .strip_prefix(self.key_value_separator)
+            .unwrap_or("");
+
+        let value_end = token_end(rest, self.entry_separator, self.quote);
+        self.rest = &rest[value_end..];
+
+        Some((key, &rest[..value_end]))
+    }
+}
+
+/// The index of the first `stop` character outside of quotes, or the length
+/// of the input.
+fn token_end(input: &str, stop: char, quote: char) -> usize {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (index, ch) in input.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            _ if ch == quote => in_quotes = !in_quotes,
+            _ if ch == stop && !in_quotes => return index,
+            _ => {}
+        }
+    }
+    input.len()
+}
+
+/// Whether a value can be written in JSON as is: a number, `true` or `false`.
+fn is_json_literal(value: &str) -> bool {
+    if matches!(value, "true" | "false") {
+        return true;
+    }
+
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    let (number, exponent) = match digits.find(['e', 'E']) {
+        Some(index) => (&digits[..index], Some(&digits[index + 1..])),
+        None => (digits, None),
+    };
+    let (whole, fraction) = match number.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (number, None),
+    };
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+
+    all_digits(whole)
+        && (whole == "0" || !whole.starts_with('0'))
+        && fraction.is_none_or(all_digits)
+        && exponent.is_none_or(|exponent| {
+            all_digits(exponent.strip_prefix(['+', '-']).unwrap_or(exponent))
+        })
+}
+
 #[inline]
 pub(crate) fn need_quote(ch: char) -> bool {
     ch <= ' ' || matches!(ch, '=' | '"')
 }
 
-impl<W> std::io::Write for Serializer<W>
+impl<W> std::io::Write for Serializer<'_, W>
 where
     W: fmt::Write,
 {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         if let Ok(buf) = std::str::from_utf8(buf) {
-            self.writer
-                .write_str(buf)
-                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+            self.writer.write_str(buf).map_err(std::io::Error::other)?;
         }
         Ok(buf.len())
     }
@@ -176,8 +779,9 @@ mod tests {
     #[test]
     #[cfg(not(feature = "ansi_logs"))]
     fn test_serialize_entries() {
+        let options = SerializerOptions::default();
         let mut output = String::new();
-        let mut s = Serializer::new(&mut output);
+        let mut s = Serializer::new(&mut output, &options);
         assert!(s.serialize_entry("key", "value").is_ok());
         assert!(s.serialize_entry("key2", "value2").is_ok());
 
@@ -196,8 +800,9 @@ mod tests {
         ];
 
         for ((k, v), expected_output) in tests {
+            let options = SerializerOptions::default();
             let mut output = String::new();
-            let mut s = Serializer::new(&mut output);
+            let mut s = Serializer::new(&mut output, &options);
             assert!(s.serialize_entry(k, v).is_ok());
             assert_eq!(output, expected_output,);
         }
@@ -224,8 +829,9 @@ mod tests {
         ];
 
         for ((k, v), expected_output) in tests {
+            let options = SerializerOptions::default();
             let mut output = String::new();
-            let mut s = Serializer::new(&mut output, true);
+            let mut s = Serializer::new(&mut output, &options, true);
             assert!(s.serialize_entry(k, v).is_ok());
             assert_eq!(output, expected_output,);
         }
@@ -240,6 +846,264 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_key_case() {
+        let tests = [
+            ("request_id", "request_id", "request-id"),
+            ("requestId", "request_id", "request-id"),
+            ("request-id", "request_id", "request-id"),
+            ("RequestId", "request_id", "request-id"),
+            ("HTTPServer", "http_server", "http-server"),
+            ("userID", "user_id", "user-id"),
+            ("ipV4Addr", "ip_v4_addr", "ip-v4-addr"),
+            ("user2Name", "user2_name", "user2-name"),
+            (
+                "error.sourceChain",
+                "error.source_chain",
+                "error.source-chain",
+            ),
+            ("foo_Bar", "foo_bar", "foo-bar"),
+            ("ÄpfelZähler", "äpfel_zähler", "äpfel-zähler"),
+        ];
+
+        for (key, snake, kebab) in tests {
+            assert_eq!(KeyCase::AsIs.convert(key), key);
+            assert_eq!(KeyCase::Snake.convert(key), snake, "{}", key);
+            assert_eq!(KeyCase::Kebab.convert(key), kebab, "{}", key);
+        }
+
+        assert!(matches!(
+            KeyCase::Snake.convert("time.busy_ns"),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(
+            KeyCase::Kebab.convert("span-path"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_serialize_entry_quoted() {
+        let tests = vec![
+            (("key", "value"), r#"key="value""#),
+            (("key", "a::b<C>"), r#"key="a::b<C>""#),
+            (("key", "val\"ue"), r#"key="val\"ue""#),
+        ];
+
+        for ((k, v), expected_output) in tests {
+            let options = SerializerOptions::default();
+            let mut output = String::new();
+            let mut s = Serializer::new(
+                &mut output,
+                &options,
+                #[cfg(feature = "ansi_logs")]
+                false,
+            );
+            assert!(s.serialize_entry_quoted(k, v).is_ok());
+            assert_eq!(output, expected_output);
+        }
+    }
+
+    #[test]
+    fn test_serialize_formatted_entries() {
+        let options = SerializerOptions::default();
+        let mut output = String::new();
+        let mut s = Serializer::new(
+            &mut output,
+            &options,
+            #[cfg(feature = "ansi_logs")]
+            false,
+        );
+        assert!(s.serialize_formatted_entries("a=1 b=2").is_ok());
+        assert!(s.serialize_entry_no_quote("c", 3).is_ok());
+        assert!(s.serialize_formatted_entries("d=4").is_ok());
+
+        assert_eq!(output, "a=1 b=2 c=3 d=4");
+    }
+
+    #[test]
+    fn test_is_json_literal() {
+        for value in ["0", "-1", "1.5", "1e10", "2.5E-3", "true", "false"] {
+            assert!(is_json_literal(value), "{}", value);
+        }
+        for value in [
+            "", "-", "01", "1.", ".5", "1e", "NaN", "inf", "True", "0x10",
+        ] {
+            assert!(!is_json_literal(value), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_formatted_entries() {
+        let tests: Vec<(&str, Vec<(&str, &str)>)> = vec![
+            ("", vec![]),
+            ("a=1", vec![("a", "1")]),
+            ("a=1 b=two", vec![("a", "1"), ("b", "two")]),
+            (
+                r#"a="x y" b="say \"hi\" =" c="#,
+                vec![("a", r#""x y""#), ("b", r#""say \"hi\" =""#), ("c", "")],
+            ),
+            (r#""my key"=1 k=v"#, vec![(r#""my key""#, "1"), ("k", "v")]),
+            (r#"a="\\" b=2"#, vec![("a", r#""\\""#), ("b", "2")]),
+        ];
+
+        for (input, expected_output) in tests {
+            assert_eq!(
+                FormattedEntries::new(input, '"').collect::<Vec<_>>(),
+                expected_output,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_serialize_value_quote_chars() {
+        let tests = vec![("a|b", r#""a|b""#), ("a,b;c", "a,b;c"), ("a b", r#""a b""#)];
+
+        for (value, expected_output) in tests {
+            let options = SerializerOptions {
+                quote_chars: vec!['|'],
+                ..Default::default()
+            };
+            let mut output = String::new();
+            let mut s = Serializer::new(
+                &mut output,
+                &options,
+                #[cfg(feature = "ansi_logs")]
+                false,
+            );
+            assert!(s.serialize_value(value).is_ok());
+            assert_eq!(output, expected_output);
+        }
+    }
+
+    #[test]
+    fn test_serialize_value_strip_newlines() {
+        let tests = vec![
+            (("a\nb", "␤"), "a␤b"),
+            (("a\r\nb\rc", "␤"), "a␤b␤c"),
+            (("a\nb", " "), r#""a b""#),
+            (("a\nb", r"\n"), r"a\nb"),
+            (("a\n\"b\"", ""), r#""a\"b\"""#),
+        ];
+
+        for ((input, placeholder), expected_output) in tests {
+            let options = SerializerOptions {
+                strip_newlines: true,
+                newline_placeholder: placeholder.to_owned(),
+                ..Default::default()
+            };
+            let mut output = String::new();
+
+            #[cfg(not(feature = "ansi_logs"))]
+            let mut s = Serializer::new(&mut output, &options);
+            #[cfg(feature = "ansi_logs")]
+            let mut s = Serializer::new(&mut output, &options, true);
+
+            assert!(s.serialize_value(input).is_ok());
+
+            assert_eq!(output, expected_output);
+        }
+    }
+
+    #[test]
+    fn test_serialize_value_minimal_escape() {
+        let tests = vec![
+            ("v", "v"),
+            (" ", r#"" ""#),
+            (r#"""#, r#""\"""#),
+            (r#"a\ b"#, r#""a\\ b""#),
+            ("\n\r\t", r#""\n\r\t""#),
+            ("a\x00 b", "\"a\x00 b\""),
+            ("a\x10b", "\"a\x10b\""),
+            ("µ åäö", r#""µ åäö""#),
+        ];
+
+        for (input, expected_output) in tests {
+            let options = SerializerOptions {
+                escape_mode: EscapeMode::Minimal,
+                ..Default::default()
+            };
+            let mut output = String::new();
+
+            #[cfg(not(feature = "ansi_logs"))]
+            let mut s = Serializer::new(&mut output, &options);
+            #[cfg(feature = "ansi_logs")]
+            let mut s = Serializer::new(&mut output, &options, true);
+
+            assert!(s.serialize_value(input).is_ok());
+
+            assert_eq!(output, expected_output);
+        }
+    }
+
+    #[test]
+    fn test_serialize_value_escape_equals() {
+        let tests = vec![
+            (EscapeMode::Debug, "a=b&c=d", r#""a\=b&c\=d""#),
+            (EscapeMode::Minimal, "a=b&c=d", r#""a\=b&c\=d""#),
+            (EscapeMode::Debug, "=\"=", r#""\=\"\=""#),
+            (EscapeMode::Minimal, "a\\=b", r#""a\\\=b""#),
+            (EscapeMode::Debug, "no equals", r#""no equals""#),
+        ];
+
+        for (escape_mode, input, expected_output) in tests {
+            let options = SerializerOptions {
+                escape_mode,
+                escape_equals: true,
+                ..Default::default()
+            };
+            let mut output = String::new();
+
+            let mut s = Serializer::new(
+                &mut output,
+                &options,
+                #[cfg(feature = "ansi_logs")]
+                false,
+            );
+
+            assert!(s.serialize_entry("url", input).is_ok());
+
+            assert_eq!(output, format!("url={}", expected_output));
+        }
+    }
+
+    #[test]
+    fn test_serialize_value_quote_char() {
+        let tests = vec![
+            (EscapeMode::Debug, "a b", "'a b'"),
+            (EscapeMode::Debug, r#"it's "x""#, r#"'it\'s "x"'"#),
+            (EscapeMode::Debug, "'\n'", r"'\'\n\''"),
+            (EscapeMode::Minimal, r#"it's "x""#, r#"'it\'s "x"'"#),
+            (EscapeMode::Debug, "plain", "plain"),
+            (EscapeMode::Debug, "don't", r"'don\'t'"),
+        ];
+
+        for (escape_mode, input, expected_output) in tests {
+            let options = SerializerOptions {
+                escape_mode,
+                quote_char: '\'',
+                ..Default::default()
+            };
+            let mut output = String::new();
+
+            #[cfg(not(feature = "ansi_logs"))]
+            let mut s = Serializer::new(&mut output, &options);
+            #[cfg(feature = "ansi_logs")]
+            let mut s = Serializer::new(&mut output, &options, true);
+
+            assert!(s.serialize_value(input).is_ok());
+
+            assert_eq!(output, expected_output, "{}", input);
+        }
+
+        assert_eq!(
+            FormattedEntries::new("a='x y' b='it\\'s' c=\"", '\'').collect::<Vec<_>>(),
+            vec![("a", "'x y'"), ("b", r"'it\'s'"), ("c", "\"")]
+        );
+    }
+
     #[test]
     #[cfg(not(feature = "ansi_logs"))]
     fn test_serialize_key() {
@@ -251,15 +1115,68 @@ mod tests {
             ("k\ney", "key"),
         ];
         for (input, expected_output) in tests {
+            let options = SerializerOptions::default();
             let mut output = String::new();
 
-            let mut s = Serializer::new(&mut output);
+            let mut s = Serializer::new(&mut output, &options);
             assert!(s.serialize_key(input).is_ok());
 
             assert_eq!(output, expected_output);
         }
     }
 
+    #[test]
+    #[cfg(not(feature = "ansi_logs"))]
+    fn test_serialize_quoted_key() {
+        let tests = vec![
+            ("key", "key"),
+            ("k ey", r#""k ey""#),
+            ("k\"ey", r#""k\"ey""#),
+            ("k=ey", r#""k=ey""#),
+            ("k\ney", r#""k\ney""#),
+        ];
+        for (input, expected_output) in tests {
+            let options = SerializerOptions {
+                quote_keys: true,
+                ..Default::default()
+            };
+            let mut output = String::new();
+
+            let mut s = Serializer::new(&mut output, &options);
+            assert!(s.serialize_key(input).is_ok());
+
+            assert_eq!(output, expected_output);
+        }
+
+        let options = SerializerOptions {
+            quote_keys: true,
+            ..Default::default()
+        };
+        let mut output = String::new();
+        let mut s = Serializer::new(&mut output, &options);
+        assert_eq!(s.serialize_key(""), Err(SerializerError::InvalidKey));
+    }
+
+    #[test]
+    fn test_serialize_invalid_key_writes_nothing() {
+        let options = SerializerOptions::default();
+        let mut output = String::new();
+        let mut s = Serializer::new(
+            &mut output,
+            &options,
+            #[cfg(feature = "ansi_logs")]
+            false,
+        );
+        assert!(s.serialize_entry_no_quote("a", 1).is_ok());
+        assert_eq!(
+            s.serialize_entry_no_quote("=", 2),
+            Err(SerializerError::InvalidKey)
+        );
+        assert!(s.serialize_entry_no_quote("b", 3).is_ok());
+
+        assert_eq!(output, "a=1 b=3");
+    }
+
     #[test]
     fn test_serialize_key_invalid() {
         let tests = vec![
@@ -270,12 +1187,13 @@ mod tests {
         ];
 
         for (input, expected_error) in tests {
+            let options = SerializerOptions::default();
             let mut output = String::new();
 
             #[cfg(not(feature = "ansi_logs"))]
-            let mut s = Serializer::new(&mut output);
+            let mut s = Serializer::new(&mut output, &options);
             #[cfg(feature = "ansi_logs")]
-            let mut s = Serializer::new(&mut output, true);
+            let mut s = Serializer::new(&mut output, &options, true);
             assert_eq!(s.serialize_key(input), Err(expected_error));
         }
     }
@@ -296,15 +1214,21 @@ mod tests {
             ("\x1F", r#""\u{1f}""#),
             ("µ", r#"µ"#),
             ("åäö", r#"åäö"#),
+            ("a b", r#""a b""#),
+            ("ab\n", r#""ab\n""#),
+            (r#"a\b c"#, r#""a\\b c""#),
+            ("it's here", r#""it\'s here""#),
+            ("åäö x", r#""åäö x""#),
         ];
 
         for (input, expected_output) in tests {
+            let options = SerializerOptions::default();
             let mut output = String::new();
 
             #[cfg(not(feature = "ansi_logs"))]
-            let mut s = Serializer::new(&mut output);
+            let mut s = Serializer::new(&mut output, &options);
             #[cfg(feature = "ansi_logs")]
-            let mut s = Serializer::new(&mut output, true);
+            let mut s = Serializer::new(&mut output, &options, true);
 
             assert!(s.serialize_value(input).is_ok());
 
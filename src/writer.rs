@@ -0,0 +1,354 @@
+use std::io;
+
+use crate::serializer::{Serializer, SerializerError, SerializerOptions};
+
+/// Writes logfmt lines to an [`io::Write`], without going through tracing.
+///
+/// Entries are quoted and escaped the same way as by the tracing formatter.
+/// A line is kept in memory until [`LogfmtWriter::finish_line`] is called,
+/// which writes it, and a trailing newline, to the underlying writer in one
+/// call.
+///
+/// Example:
+/// ```rust
+/// let mut writer = tracing_logfmt::LogfmtWriter::new(Vec::new());
+/// writer.write_entry("key", "value")?;
+/// writer.write_entry("other", "with spaces")?;
+/// writer.finish_line()?;
+///
+/// assert_eq!(writer.get_ref(), b"key=value other=\"with spaces\"\n");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct LogfmtWriter<W> {
+    writer: W,
+    options: SerializerOptions,
+    line: String,
+}
+
+impl<W> LogfmtWriter<W>
+where
+    W: io::Write,
+{
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            options: SerializerOptions::default(),
+            line: String::new(),
+        }
+    }
+
+    /// Adds an entry to the current line.
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidInput`] if the key is
+    /// empty after removing the characters that are not allowed in keys.
+    pub fn write_entry(&mut self, key: &str, value: &str) -> io::Result<()> {
+        let line_len = self.line.len();
+
+        let mut serializer = Serializer::new(
+            &mut self.line,
+            &self.options,
+            #[cfg(feature = "ansi_logs")]
+            false,
+        );
+        if line_len > 0 {
+            serializer.continue_line();
+        }
+
+        serializer.serialize_entry(key, value).map_err(|err| {
+            // don't leave a partial entry behind
+            self.line.truncate(line_len);
+            match err {
+                SerializerError::InvalidKey => {
+                    io::Error::new(io::ErrorKind::InvalidInput, "invalid logfmt key")
+                }
+                SerializerError::FmtError => io::Error::other("failed to format entry"),
+            }
+        })
+    }
+
+    /// Ends the current line and writes it to the underlying writer.
+    pub fn finish_line(&mut self) -> io::Result<()> {
+        self.line.push('\n');
+        let result = self.writer.write_all(self.line.as_bytes());
+        self.line.clear();
+        result
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Returns the underlying writer. Entries of an unfinished line are lost.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Wraps an [`io::Write`] and drops every `ts=` entry after the first one of
+/// each line.
+///
+/// Useful when the lines pass through something else that already prepends a
+/// timestamp. If that can be changed, prefer turning the timestamp of this
+/// crate off with [`Builder::with_timestamp`](crate::Builder::with_timestamp).
+///
+/// Input is buffered until the end of the line, so that an entry is never
+/// split between writes. A partial line is written on [`io::Write::flush`]
+/// and when the writer is dropped.
+///
+/// Entries are separated by spaces outside of quoted values. Lines written
+/// with another quote char than `"`, see
+/// [`Builder::with_quote_char`](crate::Builder::with_quote_char), need the
+/// same one set with [`DedupTimestampWriter::with_quote_char`].
+///
+/// Example:
+/// ```rust
+/// use std::io::Write;
+///
+/// let mut output = Vec::new();
+/// let mut writer = tracing_logfmt::DedupTimestampWriter::new(&mut output);
+/// writer.write_all(b"ts=2024-01-01T00:00:00Z ts=2024-01-01T00:00:00.123Z level=info\n")?;
+/// drop(writer);
+///
+/// assert_eq!(output, b"ts=2024-01-01T00:00:00Z level=info\n");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct DedupTimestampWriter<W>
+where
+    W: io::Write,
+{
+    writer: W,
+    line: Vec<u8>,
+    quote_char: char,
+}
+
+impl<W> DedupTimestampWriter<W>
+where
+    W: io::Write,
+{
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            line: Vec::new(),
+            quote_char: '"',
+        }
+    }
+
+    /// Sets the character that values are quoted with, to find where the
+    /// entries of a line end. Defaults to `"`.
+    pub fn with_quote_char(mut self, quote: char) -> Self {
+        self.quote_char = quote;
+        self
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Writes the buffered line, without the repeated timestamps.
+    fn write_line(&mut self) -> io::Result<()> {
+        let line = std::mem::take(&mut self.line);
+        let mut deduped = Vec::with_capacity(line.len());
+        let mut seen_ts = false;
+        let mut start = 0;
+        let mut quote = [0; 4];
+        let quote = self.quote_char.encode_utf8(&mut quote).as_bytes();
+
+        while start < line.len() {
+            let end = entry_end(&line, start, quote);
+            let entry = &line[start..end];
+            let is_ts = entry.starts_with(b"ts=");
+            if !is_ts || !seen_ts {
+                if !deduped.is_empty() && start > 0 {
+                    deduped.push(b' ');
+                }
+                deduped.extend_from_slice(entry);
+            }
+            seen_ts |= is_ts;
+            // skip the separating space
+            start = end + 1;
+        }
+        if line.ends_with(b"\n") && !deduped.ends_with(b"\n") {
+            deduped.push(b'\n');
+        }
+
+        self.line = line;
+        self.line.clear();
+        self.writer.write_all(&deduped)
+    }
+}
+
+/// The end of the entry that starts at `start`: the next space that is not
+/// inside quotes, or the end of the line. `quote` is the UTF-8 encoded quote
+/// char.
+fn entry_end(line: &[u8], start: usize, quote: &[u8]) -> usize {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut i = start;
+    while i < line.len() {
+        match line[i] {
+            _ if escaped => escaped = false,
+            b'\\' if in_quotes => escaped = true,
+            _ if line[i..].starts_with(quote) => {
+                in_quotes = !in_quotes;
+                i += quote.len();
+                continue;
+            }
+            b' ' if !in_quotes => return i,
+            _ => {}
+        }
+        i += 1;
+    }
+    line.len()
+}
+
+impl<W> io::Write for DedupTimestampWriter<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while let Some(newline) = rest.iter().position(|&byte| byte == b'\n') {
+            let (line, after) = rest.split_at(newline + 1);
+            self.line.extend_from_slice(line);
+            self.write_line()?;
+            rest = after;
+        }
+        self.line.extend_from_slice(rest);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            self.write_line()?;
+        }
+        self.writer.flush()
+    }
+}
+
+impl<W> Drop for DedupTimestampWriter<W>
+where
+    W: io::Write,
+{
+    fn drop(&mut self) {
+        // like `BufWriter`, errors can't be reported here
+        let _ = io::Write::flush(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_lines() {
+        let mut writer = LogfmtWriter::new(Vec::new());
+
+        writer.write_entry("key", "value").unwrap();
+        writer.write_entry("ke y", "val ue").unwrap();
+        writer.finish_line().unwrap();
+        writer.write_entry("second", "line").unwrap();
+        writer.finish_line().unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(writer.get_ref()).unwrap(),
+            "key=value key=\"val ue\"\nsecond=line\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let mut writer = LogfmtWriter::new(Vec::new());
+
+        writer.write_entry("key", "value").unwrap();
+        let err = writer.write_entry(" ", "value").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        writer.finish_line().unwrap();
+
+        assert_eq!(writer.into_inner(), b"key=value\n");
+    }
+
+    #[test]
+    fn test_io_error() {
+        struct FailingWriter;
+
+        impl io::Write for FailingWriter {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut writer = LogfmtWriter::new(FailingWriter);
+        writer.write_entry("key", "value").unwrap();
+
+        let err = writer.finish_line().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn test_dedup_timestamp() {
+        use io::Write as _;
+
+        let tests = [
+            ("ts=1 level=info ts=2\n", "ts=1 level=info\n"),
+            ("prefix ts=1 ts=2 message=hi\n", "prefix ts=1 message=hi\n"),
+            ("level=info message=hi\n", "level=info message=hi\n"),
+            (
+                "ts=1 message=\"ts=2 ts=3\" ts=4 x=\"\\\" ts=5\"\n",
+                "ts=1 message=\"ts=2 ts=3\" x=\"\\\" ts=5\"\n",
+            ),
+            ("ts=1 ts=2\nts=3 ts=4\n", "ts=1\nts=3\n"),
+            ("ts=1 ts=2", "ts=1"),
+        ];
+
+        for (input, expected_output) in tests {
+            let mut output = Vec::new();
+            let mut writer = DedupTimestampWriter::new(&mut output);
+            // split the input to check that entries are buffered
+            let (first, second) = input.split_at(input.len() / 2);
+            writer.write_all(first.as_bytes()).unwrap();
+            writer.write_all(second.as_bytes()).unwrap();
+            drop(writer);
+
+            assert_eq!(std::str::from_utf8(&output).unwrap(), expected_output);
+        }
+    }
+
+    #[test]
+    fn test_dedup_timestamp_quote_char() {
+        use io::Write as _;
+
+        for (quote, input, expected_output) in [
+            (
+                '\'',
+                "ts=1 message='a b ts=2' ts=3\n",
+                "ts=1 message='a b ts=2'\n",
+            ),
+            (
+                '§',
+                "ts=1 message=§a ts=2§ ts=3\n",
+                "ts=1 message=§a ts=2§\n",
+            ),
+        ] {
+            let mut output = Vec::new();
+            let mut writer = DedupTimestampWriter::new(&mut output).with_quote_char(quote);
+            writer.write_all(input.as_bytes()).unwrap();
+            drop(writer);
+
+            assert_eq!(std::str::from_utf8(&output).unwrap(), expected_output);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Checks that formatting an event doesn't allocate once the thread-local
+//! buffers are warmed up.
+//!
+//! This is its own test binary because the counting allocator replaces the
+//! global allocator of the whole process.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io;
+
+/// Counts the allocations made by the current thread, so that tests
+/// running in parallel don't affect each other.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// a global allocator can't be implemented without `unsafe`; this one
+// forwards everything to the system allocator
+#[allow(unsafe_code)]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The number of allocations made by `f` after a warm-up call.
+fn allocations(f: impl Fn()) -> usize {
+    let subscriber = tracing_logfmt::builder()
+        .subscriber_builder()
+        .with_writer(io::sink)
+        .finish();
+
+    tracing::subscriber::with_default(subscriber, || {
+        f();
+
+        let before = ALLOCATIONS.with(|count| count.get());
+        for _ in 0..100 {
+            f();
+        }
+        ALLOCATIONS.with(|count| count.get()) - before
+    })
+}
+
+#[test]
+fn test_debug_fmt_buffer_is_reused() {
+    let point = (1, 2);
+    assert_eq!(allocations(|| tracing::info!(?point, "a")), 0);
+}
+
+#[test]
+fn test_quoted_str_does_not_allocate() {
+    assert_eq!(
+        allocations(|| tracing::info!(text = "hello world", path = r"C:\logs \ old", "a")),
+        0
+    );
+}